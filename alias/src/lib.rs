@@ -0,0 +1,320 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use bril_rs::{Code, EffectOps, Function, Instruction, Program, ValueOps};
+use util::SafeAccess;
+
+/// An alloc instruction, standing for every object it creates
+#[derive(Clone, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+pub struct Site {
+    pub function: String,
+    /// Position of the alloc among the function's instructions and labels
+    pub index: usize,
+    pub dest: String,
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.function, self.dest, self.index)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum Node {
+    Var(String, String),
+    /// Pointers stored anywhere in the objects of a site
+    Heap(Site),
+    /// Values returned by a function
+    Ret(String),
+}
+
+enum Constraint {
+    /// pts(node) contains site
+    Alloc(Node, Site),
+    /// pts(to) contains pts(from)
+    Copy { from: Node, to: Node },
+    /// pts(to) contains the contents of every object pts(ptr) points to
+    Load { ptr: Node, to: Node },
+    /// Every object pts(ptr) points to contains pts(from)
+    Store { from: Node, ptr: Node },
+}
+
+/// Flow insensitive, allocation site based points-to sets for every variable of a program,
+/// solved with inclusion constraints across calls
+pub struct PointsTo {
+    sets: HashMap<Node, HashSet<Site>>,
+    /// Variables mapped to the variable they copy through chains of single-definition ids,
+    /// holding the same value wherever the copy is read
+    roots: HashMap<(String, String), String>,
+}
+
+impl PointsTo {
+    pub fn analyze(program: &Program) -> Self {
+        let functions: HashMap<_, _> = program
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f))
+            .collect();
+        let mut constraints = Vec::new();
+        for function in &program.functions {
+            collect_constraints(function, &functions, &mut constraints);
+        }
+
+        let mut sets: HashMap<Node, HashSet<Site>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for constraint in &constraints {
+                let (to, sites): (Node, HashSet<Site>) = match constraint {
+                    Constraint::Alloc(node, site) => (node.clone(), HashSet::from([site.clone()])),
+                    Constraint::Copy { from, to } => {
+                        (to.clone(), sets.get(from).cloned().unwrap_or_default())
+                    }
+                    Constraint::Load { ptr, to } => {
+                        let contents = sets
+                            .get(ptr)
+                            .into_iter()
+                            .flatten()
+                            .flat_map(|site| sets.get(&Node::Heap(site.clone())))
+                            .flatten()
+                            .cloned()
+                            .collect();
+                        (to.clone(), contents)
+                    }
+                    Constraint::Store { from, ptr } => {
+                        let stored = sets.get(from).cloned().unwrap_or_default();
+                        for site in sets.get(ptr).cloned().unwrap_or_default() {
+                            let set = sets.entry(Node::Heap(site)).or_default();
+                            let before = set.len();
+                            set.extend(stored.iter().cloned());
+                            changed |= set.len() > before;
+                        }
+                        continue;
+                    }
+                };
+                let set = sets.entry(to).or_default();
+                let before = set.len();
+                set.extend(sites);
+                changed |= set.len() > before;
+            }
+        }
+
+        let roots = program.functions.iter().flat_map(copy_roots).collect();
+        PointsTo { sets, roots }
+    }
+
+    /// Returns the allocation sites var may point into
+    pub fn points_to(&self, function: &str, var: &str) -> HashSet<Site> {
+        self.sets
+            .get(&Node::Var(function.to_string(), var.to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the allocation sites whose objects may hold a pointer into an object of site
+    pub fn heap_contents(&self, site: &Site) -> HashSet<Site> {
+        self.sets
+            .get(&Node::Heap(site.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether a and b may point into the same object
+    pub fn may_alias(&self, function: &str, a: &str, b: &str) -> bool {
+        self.must_alias(function, a, b)
+            || !self
+                .points_to(function, a)
+                .is_disjoint(&self.points_to(function, b))
+    }
+
+    /// Whether a and b hold the same address at every instruction reading both
+    pub fn must_alias(&self, function: &str, a: &str, b: &str) -> bool {
+        if a == b {
+            return true;
+        }
+        let root = |var: &str| self.roots.get(&(function.to_string(), var.to_string()));
+        matches!((root(a), root(b)), (Some(x), Some(y)) if x == y)
+    }
+}
+
+fn var(function: &Function, name: &str) -> Node {
+    Node::Var(function.name.clone(), name.to_string())
+}
+
+fn collect_constraints(
+    function: &Function,
+    functions: &HashMap<&str, &Function>,
+    constraints: &mut Vec<Constraint>,
+) {
+    for (index, code) in function.instrs.iter().enumerate() {
+        let Code::Instruction(instr) = code else {
+            continue;
+        };
+        match instr {
+            Instruction::Value {
+                op: ValueOps::Alloc,
+                dest,
+                ..
+            } => constraints.push(Constraint::Alloc(
+                var(function, dest),
+                Site {
+                    function: function.name.clone(),
+                    index,
+                    dest: dest.clone(),
+                },
+            )),
+            Instruction::Value {
+                op: ValueOps::Id | ValueOps::PtrAdd | ValueOps::Phi,
+                dest,
+                args,
+                ..
+            } => {
+                // The offset of a ptradd is an int, so only the base flows into dest
+                let sources = match instr {
+                    Instruction::Value {
+                        op: ValueOps::PtrAdd,
+                        ..
+                    } => &args[..1],
+                    _ => &args[..],
+                };
+                for arg in sources {
+                    constraints.push(Constraint::Copy {
+                        from: var(function, arg),
+                        to: var(function, dest),
+                    });
+                }
+            }
+            Instruction::Value {
+                op: ValueOps::Load,
+                dest,
+                args,
+                ..
+            } => constraints.push(Constraint::Load {
+                ptr: var(function, &args[0]),
+                to: var(function, dest),
+            }),
+            Instruction::Effect {
+                op: EffectOps::Store,
+                args,
+                ..
+            } => constraints.push(Constraint::Store {
+                from: var(function, &args[1]),
+                ptr: var(function, &args[0]),
+            }),
+            Instruction::Effect {
+                op: EffectOps::Return,
+                args,
+                ..
+            } => {
+                for arg in args {
+                    constraints.push(Constraint::Copy {
+                        from: var(function, arg),
+                        to: Node::Ret(function.name.clone()),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        if let Instruction::Value {
+            op: ValueOps::Call,
+            funcs,
+            args,
+            ..
+        }
+        | Instruction::Effect {
+            op: EffectOps::Call,
+            funcs,
+            args,
+            ..
+        } = instr
+        {
+            let Some(callee) = funcs.first().and_then(|name| functions.get(name.as_str())) else {
+                continue;
+            };
+            for (arg, param) in args.iter().zip(&callee.args) {
+                constraints.push(Constraint::Copy {
+                    from: var(function, arg),
+                    to: var(callee, &param.name),
+                });
+            }
+            if let Some(dest) = instr.get_dest() {
+                constraints.push(Constraint::Copy {
+                    from: Node::Ret(callee.name.clone()),
+                    to: var(function, &dest),
+                });
+            }
+        }
+    }
+}
+
+/// Maps every variable defined once to the variable it copies through ids, as long as each
+/// copy comes after its source in the same block so both are always reassigned together.
+/// A copy read between the definitions still holds the previous value, so the chain stops there
+fn copy_roots(function: &Function) -> Vec<((String, String), String)> {
+    let mut defs: HashMap<String, Vec<(usize, usize, &Instruction)>> = HashMap::new();
+    let mut block = 0;
+    for (index, code) in function.instrs.iter().enumerate() {
+        match code {
+            Code::Label { .. } => block += 1,
+            Code::Instruction(instr) => {
+                if let Some(dest) = instr.get_dest() {
+                    defs.entry(dest).or_default().push((block, index, instr));
+                }
+                if matches!(
+                    instr,
+                    Instruction::Effect {
+                        op: EffectOps::Jump | EffectOps::Branch | EffectOps::Return,
+                        ..
+                    }
+                ) {
+                    block += 1;
+                }
+            }
+        }
+    }
+
+    defs.iter()
+        .filter(|(_, sites)| sites.len() == 1)
+        .map(|(v, sites)| {
+            let (_, v_index, _) = sites[0];
+            let read_after = |index: usize| {
+                function.instrs[index + 1..v_index]
+                    .iter()
+                    .any(|code| match code {
+                        Code::Instruction(instr) => {
+                            instr.get_args().is_some_and(|args| args.contains(v))
+                        }
+                        Code::Label { .. } => false,
+                    })
+            };
+            let mut root = v.clone();
+            while let Some(
+                [(
+                    block,
+                    index,
+                    Instruction::Value {
+                        op: ValueOps::Id,
+                        args,
+                        ..
+                    },
+                )],
+            ) = defs.get(&root).map(Vec::as_slice)
+            {
+                match defs.get(&args[0]).map(Vec::as_slice) {
+                    Some([(source_block, source_index, _)])
+                        if source_block == block
+                            && source_index < index
+                            && !read_after(*source_index) =>
+                    {
+                        root = args[0].clone()
+                    }
+                    _ => break,
+                }
+            }
+            ((function.name.clone(), v.clone()), root)
+        })
+        .collect()
+}
@@ -0,0 +1,67 @@
+use std::{env, io};
+
+use alias::{PointsTo, Site};
+use bril_rs::{load_program_from_read, Code};
+use itertools::Itertools;
+use util::SafeAccess;
+
+fn format_sites<'a>(sites: impl IntoIterator<Item = &'a Site>) -> String {
+    sites.into_iter().sorted().join(", ")
+}
+
+fn main() {
+    let alias_output = env::args().any(|arg| arg == "--alias");
+
+    let program = load_program_from_read(io::stdin());
+    let points_to = PointsTo::analyze(&program);
+
+    for function in &program.functions {
+        println!("@{}", function.name);
+        let vars: Vec<_> = function
+            .args
+            .iter()
+            .map(|arg| arg.name.clone())
+            .chain(function.instrs.iter().flat_map(|code| match code {
+                Code::Instruction(instr) => instr.get_dest(),
+                Code::Label { .. } => None,
+            }))
+            .unique()
+            .filter(|var| !points_to.points_to(&function.name, var).is_empty())
+            .collect();
+        for var in &vars {
+            let sites = points_to.points_to(&function.name, var);
+            println!("  {}: {}", var, format_sites(&sites));
+        }
+        if alias_output {
+            for (a, b) in vars.iter().tuple_combinations() {
+                if points_to.must_alias(&function.name, a, b) {
+                    println!("  {} {}: must", a, b);
+                } else if points_to.may_alias(&function.name, a, b) {
+                    println!("  {} {}: may", a, b);
+                }
+            }
+        }
+    }
+
+    let sites = program
+        .functions
+        .iter()
+        .flat_map(|function| {
+            function
+                .instrs
+                .iter()
+                .flat_map(|code| match code {
+                    Code::Instruction(instr) => instr.get_dest(),
+                    Code::Label { .. } => None,
+                })
+                .flat_map(|var| points_to.points_to(&function.name, &var))
+        })
+        .unique()
+        .sorted();
+    for site in sites {
+        let contents = points_to.heap_contents(&site);
+        if !contents.is_empty() {
+            println!("{} -> {}", site, format_sites(&contents));
+        }
+    }
+}
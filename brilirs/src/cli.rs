@@ -16,7 +16,7 @@ pub struct Cli {
   #[arg(short, long, action)]
   pub file: Option<String>,
 
-  /// Flag to only typecheck/validate the bril program
+  /// Flag to only typecheck/validate the bril program, including reads of variables that may be unassigned
   #[arg(short, long, action)]
   pub check: bool,
 
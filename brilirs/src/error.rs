@@ -55,6 +55,8 @@ pub enum InterpError {
   BadAsmtType(bril_rs::Type, bril_rs::Type), // (expected, actual). For when the LHS type of an instruction is bad
   #[error("There has been an io error: `{0:?}`")]
   IoError(#[from] std::io::Error),
+  #[error("{}", .0.join("\n"))]
+  PossiblyUninitialized(Vec<String>),
   #[error("value ${0} cannot be converted to char")]
   ToCharError(i64),
   #[error("You probably shouldn't see this error, this is here to handle conversions between InterpError and PositionalError")]
@@ -9,7 +9,7 @@
 
 use basic_block::BBProgram;
 use bril_rs::Program;
-use error::PositionalInterpError;
+use error::{InterpError, PositionalInterpError};
 
 /// The internal representation of brilirs, provided a ```TryFrom<Program>``` conversion
 pub mod basic_block;
@@ -42,12 +42,33 @@ pub fn run_input<T: std::io::Write, U: std::io::Write>(
   } else {
     bril_rs::load_abstract_program_from_read(input).try_into()?
   };
+  let uses = if check {
+    uninitialized_uses(&prog)
+  } else {
+    Vec::new()
+  };
   let bbprog: BBProgram = prog.try_into()?;
   check::type_check(&bbprog)?;
 
+  // Every message carries its own position
+  if !uses.is_empty() {
+    let messages = uses.iter().map(ToString::to_string).collect();
+    return Err(InterpError::PossiblyUninitialized(messages).add_pos(None));
+  }
+
   if !check {
     interp::execute_main(&bbprog, out, input_args, garbage_collect, profiling, profiling_out)?;
   }
 
   Ok(())
 }
+
+/// Runs the definite assignment analysis of `df` over every function that forms a valid cfg
+fn uninitialized_uses(prog: &Program) -> Vec<df::definite_assignment::UninitializedUse> {
+  prog
+    .functions
+    .iter()
+    .filter_map(|function| cfg::generate_cfg(function).ok())
+    .flat_map(|cfg| df::definite_assignment::uninitialized_uses(&cfg))
+    .collect()
+}
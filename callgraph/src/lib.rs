@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::{BitOr, BitOrAssign},
+};
+
+use bril_rs::{Code, EffectOps, Function, Instruction, Program, ValueOps};
+use cfg::generate_cfg;
+use itertools::Itertools;
+use petgraph::{
+    algo::{is_cyclic_directed, tarjan_scc},
+    graphmap::DiGraphMap,
+    Direction::{Incoming, Outgoing},
+};
+
+/// Returns the function called by instr, if it is a call
+pub fn callee(instr: &Instruction) -> Option<&str> {
+    match instr {
+        Instruction::Value {
+            op: ValueOps::Call,
+            funcs,
+            ..
+        }
+        | Instruction::Effect {
+            op: EffectOps::Call,
+            funcs,
+            ..
+        } => funcs.first().map(String::as_str),
+        _ => None,
+    }
+}
+
+fn instructions(function: &Function) -> impl Iterator<Item = &Instruction> {
+    function.instrs.iter().filter_map(|code| match code {
+        Code::Instruction(instr) => Some(instr),
+        Code::Label { .. } => None,
+    })
+}
+
+/// Functions of a program as nodes indexed like program.functions,
+/// with an edge from every function to each function it calls
+pub struct CallGraph {
+    pub graph: DiGraphMap<usize, ()>,
+    pub names: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl CallGraph {
+    pub fn new(program: &Program) -> Self {
+        let names: Vec<_> = program.functions.iter().map(|f| f.name.clone()).collect();
+        let index: HashMap<_, _> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect();
+        let mut graph = DiGraphMap::new();
+        for (caller, function) in program.functions.iter().enumerate() {
+            graph.add_node(caller);
+            for callee in instructions(function).filter_map(callee) {
+                if let Some(&callee) = index.get(callee) {
+                    graph.add_edge(caller, callee, ());
+                }
+            }
+        }
+        CallGraph {
+            graph,
+            names,
+            index,
+        }
+    }
+
+    /// Returns the index of the function called name in the program
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Returns the functions name calls, in program order
+    pub fn callees(&self, name: &str) -> Vec<&str> {
+        self.neighbors(name, Outgoing)
+    }
+
+    /// Returns the functions calling name, in program order
+    pub fn callers(&self, name: &str) -> Vec<&str> {
+        self.neighbors(name, Incoming)
+    }
+
+    fn neighbors(&self, name: &str, direction: petgraph::Direction) -> Vec<&str> {
+        let Some(i) = self.index(name) else {
+            return Vec::new();
+        };
+        self.graph
+            .neighbors_directed(i, direction)
+            .sorted()
+            .map(|j| self.names[j].as_str())
+            .collect()
+    }
+
+    /// Strongly connected components as function indices, every component coming after the
+    /// components it calls into
+    pub fn sccs(&self) -> Vec<Vec<usize>> {
+        tarjan_scc(&self.graph)
+            .into_iter()
+            .map(|scc| scc.into_iter().sorted().collect())
+            .collect()
+    }
+
+    /// Whether a call from a function of scc can lead back into scc
+    pub fn is_recursive_scc(&self, scc: &[usize]) -> bool {
+        match scc {
+            [f] => self.graph.contains_edge(*f, *f),
+            _ => true,
+        }
+    }
+
+    /// Whether name can call itself, directly or through other functions
+    pub fn is_recursive(&self, name: &str) -> bool {
+        let Some(i) = self.index(name) else {
+            return false;
+        };
+        self.sccs()
+            .iter()
+            .find(|scc| scc.contains(&i))
+            .is_some_and(|scc| self.is_recursive_scc(scc))
+    }
+}
+
+/// What running a function may do besides computing its return value
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Summary {
+    pub reads_memory: bool,
+    /// Stores, allocations and frees
+    pub writes_memory: bool,
+    pub prints: bool,
+    /// Loops, recursion, or calls to functions that may not terminate
+    pub may_not_terminate: bool,
+}
+
+impl Summary {
+    /// What a call to a function outside the program is assumed to do
+    pub const UNKNOWN: Summary = Summary {
+        reads_memory: true,
+        writes_memory: true,
+        prints: true,
+        may_not_terminate: true,
+    };
+
+    /// Whether calls can be removed when their result is unused, or reused for equal arguments
+    pub fn is_pure(&self) -> bool {
+        *self == Summary::default()
+    }
+
+    /// Effects of instr itself, not counting what it calls
+    fn of_instr(instr: &Instruction) -> Summary {
+        match instr {
+            Instruction::Value {
+                op: ValueOps::Load, ..
+            } => Summary {
+                reads_memory: true,
+                ..Summary::default()
+            },
+            Instruction::Value {
+                op: ValueOps::Alloc,
+                ..
+            }
+            | Instruction::Effect {
+                op: EffectOps::Store | EffectOps::Free,
+                ..
+            } => Summary {
+                writes_memory: true,
+                ..Summary::default()
+            },
+            Instruction::Effect {
+                op: EffectOps::Print,
+                ..
+            } => Summary {
+                prints: true,
+                ..Summary::default()
+            },
+            // Speculation rolls back or aborts state, so it is kept in place like a store
+            Instruction::Effect {
+                op: EffectOps::Speculate | EffectOps::Commit | EffectOps::Guard,
+                ..
+            } => Summary {
+                writes_memory: true,
+                ..Summary::default()
+            },
+            _ => Summary::default(),
+        }
+    }
+}
+
+impl BitOr for Summary {
+    type Output = Summary;
+
+    fn bitor(self, other: Summary) -> Summary {
+        Summary {
+            reads_memory: self.reads_memory || other.reads_memory,
+            writes_memory: self.writes_memory || other.writes_memory,
+            prints: self.prints || other.prints,
+            may_not_terminate: self.may_not_terminate || other.may_not_terminate,
+        }
+    }
+}
+
+impl BitOrAssign for Summary {
+    fn bitor_assign(&mut self, other: Summary) {
+        *self = *self | other;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_pure() {
+            return write!(f, "pure");
+        }
+        let effects = [
+            (self.reads_memory, "reads memory"),
+            (self.writes_memory, "writes memory"),
+            (self.prints, "prints"),
+            (self.may_not_terminate, "may not terminate"),
+        ];
+        let effects = effects
+            .iter()
+            .filter(|(has, _)| *has)
+            .map(|(_, name)| name)
+            .join(", ");
+        write!(f, "{}", effects)
+    }
+}
+
+/// Summaries of every function of a program. Functions it does not know about get
+/// Summary::UNKNOWN, so the default is safe to use without a program
+#[derive(Clone, Debug, Default)]
+pub struct Summaries(HashMap<String, Summary>);
+
+impl Summaries {
+    /// Summarizes functions bottom up over the call graph, every function of a recursive
+    /// component sharing the effects of the whole component
+    pub fn new(program: &Program) -> Self {
+        let graph = CallGraph::new(program);
+        let mut summaries = Summaries::default();
+        for scc in graph.sccs() {
+            let mut summary = Summary {
+                may_not_terminate: graph.is_recursive_scc(&scc),
+                ..Summary::default()
+            };
+            for &i in &scc {
+                let function = &program.functions[i];
+                summary |= local_summary(function);
+                for callee in instructions(function).filter_map(callee) {
+                    let in_scc = graph.index(callee).is_some_and(|j| scc.contains(&j));
+                    if !in_scc {
+                        summary |= summaries.get(callee);
+                    }
+                }
+            }
+            for &i in &scc {
+                summaries.0.insert(graph.names[i].clone(), summary);
+            }
+        }
+        summaries
+    }
+
+    pub fn get(&self, function: &str) -> Summary {
+        self.0.get(function).copied().unwrap_or(Summary::UNKNOWN)
+    }
+
+    /// Effects of instr, including everything the function it calls may do
+    pub fn effects(&self, instr: &Instruction) -> Summary {
+        match callee(instr) {
+            Some(function) => self.get(function),
+            None => Summary::of_instr(instr),
+        }
+    }
+
+    /// Whether instr is a call to a pure function
+    pub fn is_pure_call(&self, instr: &Instruction) -> bool {
+        callee(instr).is_some_and(|function| self.get(function).is_pure())
+    }
+}
+
+/// Effects of the instructions of function, not counting calls, plus nontermination
+/// if its cfg has a cycle
+fn local_summary(function: &Function) -> Summary {
+    let Ok(cfg) = generate_cfg(function) else {
+        return Summary::UNKNOWN;
+    };
+    let mut summary = Summary {
+        may_not_terminate: is_cyclic_directed(&cfg.graph),
+        ..Summary::default()
+    };
+    for instr in instructions(function) {
+        summary |= Summary::of_instr(instr);
+    }
+    summary
+}
@@ -0,0 +1,29 @@
+use std::io;
+
+use bril_rs::load_program_from_read;
+use callgraph::{CallGraph, Summaries};
+use itertools::Itertools;
+
+fn main() {
+    let program = load_program_from_read(io::stdin());
+    let graph = CallGraph::new(&program);
+    let summaries = Summaries::new(&program);
+
+    for function in &program.functions {
+        println!("@{}", function.name);
+        println!(
+            "  calls: {}",
+            graph.callees(&function.name).iter().join(", ")
+        );
+        println!("  summary: {}", summaries.get(&function.name));
+    }
+    println!("sccs:");
+    for scc in graph.sccs() {
+        let names = scc.iter().map(|&i| &graph.names[i]).join(", ");
+        if graph.is_recursive_scc(&scc) {
+            println!("  {{ {} }} recursive", names);
+        } else {
+            println!("  {{ {} }}", names);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+use itertools::Itertools;
+use petgraph::prelude::DiGraphMap;
+
+use crate::{CFGNode, Edge, CFG};
+
+/// Builds a graphviz DOT document with one cluster per added graph
+#[derive(Default)]
+pub struct Dot {
+    clusters: Vec<String>,
+}
+
+impl Dot {
+    pub fn new() -> Self {
+        Dot::default()
+    }
+
+    /// Adds the cfg of a function, annotate returns extra lines shown under each block
+    pub fn add_cfg(&mut self, cfg: &CFG, annotate: impl Fn(CFGNode) -> Vec<String>) {
+        let id = self.clusters.len();
+        let mut body = String::new();
+        for node in cfg.graph.nodes().sorted() {
+            let mut lines = match cfg.get_block(node) {
+                Some(block) => std::iter::once(format!("{}:", block.label))
+                    .chain(block.instrs.iter().map(|instr| format!("  {}", instr)))
+                    .collect(),
+                None => vec!["return".to_string()],
+            };
+            lines.extend(annotate(node));
+            let shape = match node {
+                CFGNode::Block(_) => "box",
+                CFGNode::Return => "oval",
+            };
+            writeln!(
+                body,
+                "    {} [shape={}, label=\"{}\"];",
+                node_id(id, node),
+                shape,
+                escape_lines(&lines)
+            )
+            .unwrap();
+        }
+        for (from, to, edge) in cfg
+            .graph
+            .all_edges()
+            .sorted_by_key(|(from, to, _)| (*from, *to))
+        {
+            let label = match edge {
+                Edge::Always => String::new(),
+                Edge::Bool(b) => format!(" [label=\"{}\"]", b),
+            };
+            writeln!(
+                body,
+                "    {} -> {}{};",
+                node_id(id, from),
+                node_id(id, to),
+                label
+            )
+            .unwrap();
+        }
+        self.push_cluster(&cfg.name, body);
+    }
+
+    /// Adds a graph over the nodes of a cfg, such as its dominator tree
+    pub fn add_node_graph<E>(&mut self, title: &str, cfg: &CFG, graph: &DiGraphMap<CFGNode, E>) {
+        let id = self.clusters.len();
+        let mut body = String::new();
+        for node in graph.nodes().sorted() {
+            writeln!(
+                body,
+                "    {} [label=\"{}\"];",
+                node_id(id, node),
+                escape_lines(&[node_name(cfg, node)])
+            )
+            .unwrap();
+        }
+        for (from, to, _) in graph
+            .all_edges()
+            .sorted_by_key(|(from, to, _)| (*from, *to))
+        {
+            writeln!(body, "    {} -> {};", node_id(id, from), node_id(id, to)).unwrap();
+        }
+        self.push_cluster(title, body);
+    }
+
+    fn push_cluster(&mut self, title: &str, body: String) {
+        let id = self.clusters.len();
+        self.clusters.push(format!(
+            "  subgraph cluster_{} {{\n    label=\"{}\";\n{}  }}\n",
+            id,
+            escape(title),
+            body
+        ));
+    }
+}
+
+impl std::fmt::Display for Dot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "digraph {{")?;
+        writeln!(f, "  node [fontname=\"monospace\"];")?;
+        for cluster in &self.clusters {
+            write!(f, "{}", cluster)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn node_name(cfg: &CFG, node: CFGNode) -> String {
+    match cfg.get_block(node) {
+        Some(block) => block.label.clone(),
+        None => "return".to_string(),
+    }
+}
+
+fn node_id(cluster: usize, node: CFGNode) -> String {
+    match node {
+        CFGNode::Block(i) => format!("c{}_b{}", cluster, i),
+        CFGNode::Return => format!("c{}_ret", cluster),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Joins lines into a left justified DOT label
+fn escape_lines(lines: &[String]) -> String {
+    lines
+        .iter()
+        .flat_map(|line| line.lines())
+        .map(|line| escape(line) + "\\l")
+        .collect()
+}
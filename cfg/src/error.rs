@@ -0,0 +1,45 @@
+use bril_rs::{EffectOps, Position};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CfgError {
+    #[error("{}function `{function}` jumps to missing label `{label}`", location(.pos))]
+    MissingLabel {
+        function: String,
+        label: String,
+        pos: Option<Box<Position>>,
+    },
+    #[error(
+        "{}`{op}` in function `{function}` expects {expected} label(s), found [{}]",
+        location(.pos),
+        labels.join(", ")
+    )]
+    BadNumLabels {
+        function: String,
+        op: EffectOps,
+        expected: usize,
+        labels: Vec<String>,
+        pos: Option<Box<Position>>,
+    },
+}
+
+impl CfgError {
+    pub fn pos(&self) -> Option<&Position> {
+        match self {
+            CfgError::MissingLabel { pos, .. } | CfgError::BadNumLabels { pos, .. } => {
+                pos.as_deref()
+            }
+        }
+    }
+}
+
+/// Formats a position as a `file:row:col: ` prefix, empty if there is no position
+fn location(pos: &Option<Box<Position>>) -> String {
+    match pos.as_deref() {
+        Some(Position { pos, src, .. }) => match src {
+            Some(src) => format!("{}:{}:{}: ", src, pos.row, pos.col),
+            None => format!("{}:{}: ", pos.row, pos.col),
+        },
+        None => String::new(),
+    }
+}
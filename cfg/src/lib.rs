@@ -5,14 +5,25 @@ use std::{
 };
 
 use bbb::{form_blocks, Block, BlockHelpers};
-use bril_rs::{Argument, EffectOps, Function, Instruction};
+use bril_rs::{Argument, EffectOps, Function, Instruction, Position, Type};
 use petgraph::prelude::DiGraphMap;
 
+pub use dot::Dot;
+pub use error::CfgError;
+
+mod dot;
+mod error;
+mod lower;
+mod split;
+
 #[derive(Debug, Clone)]
 pub struct CFG {
+    pub name: String,
     pub blocks: Vec<Block>,
     pub graph: DiGraphMap<CFGNode, Edge>,
     pub args: Vec<Argument>,
+    pub return_type: Option<Type>,
+    pub pos: Option<Position>,
 }
 
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -82,6 +93,27 @@ impl CFG {
             .map(|(i, b)| (CFGNode::Block(i), RefCell::new(b)))
             .collect()
     }
+
+    /// Recomputes the graph from the block terminators, after they were retargeted
+    pub fn rebuild_graph(&mut self) -> Result<(), CfgError> {
+        self.graph = connect_blocks(&self.name, &mut self.blocks)?;
+        Ok(())
+    }
+
+    /// Removes the blocks for which keep returns false and rebuilds the graph,
+    /// the entry block must be kept
+    pub fn retain_blocks(
+        &mut self,
+        mut keep: impl FnMut(CFGNode, &Block) -> bool,
+    ) -> Result<(), CfgError> {
+        let mut index = 0;
+        self.blocks.retain(|block| {
+            let node = CFGNode::Block(index);
+            index += 1;
+            keep(node, block)
+        });
+        self.rebuild_graph()
+    }
 }
 
 impl Display for CFG {
@@ -97,7 +129,7 @@ impl Display for CFG {
     }
 }
 
-fn generate_label_index(blocks: &Vec<Block>) -> HashMap<String, usize> {
+fn generate_label_index(blocks: &[Block]) -> HashMap<String, usize> {
     blocks
         .iter()
         .enumerate()
@@ -105,11 +137,47 @@ fn generate_label_index(blocks: &Vec<Block>) -> HashMap<String, usize> {
         .collect()
 }
 
-pub fn generate_cfg(function: &Function) -> CFG {
-    let mut blocks = form_blocks(function);
-    let label_index = generate_label_index(&blocks);
-    let mut graph = DiGraphMap::new();
+/// Looks up the blocks targeted by a jump or branch, checking the number of labels
+fn resolve_targets(
+    function: &str,
+    label_index: &HashMap<String, usize>,
+    instr: &Instruction,
+    op: EffectOps,
+    labels: &[String],
+    expected: usize,
+) -> Result<Vec<CFGNode>, CfgError> {
+    if labels.len() != expected {
+        return Err(CfgError::BadNumLabels {
+            function: function.to_string(),
+            op,
+            expected,
+            labels: labels.to_vec(),
+            pos: instr.get_pos().map(Box::new),
+        });
+    }
+    labels
+        .iter()
+        .map(|label| {
+            label_index
+                .get(label)
+                .map(|&i| CFGNode::Block(i))
+                .ok_or_else(|| CfgError::MissingLabel {
+                    function: function.to_string(),
+                    label: label.clone(),
+                    pos: instr.get_pos().map(Box::new),
+                })
+        })
+        .collect()
+}
 
+/// Adds an edge for every block terminator, appending a jump or return
+/// to blocks that fall through
+fn connect_blocks(
+    function: &str,
+    blocks: &mut [Block],
+) -> Result<DiGraphMap<CFGNode, Edge>, CfgError> {
+    let label_index = generate_label_index(blocks);
+    let mut graph = DiGraphMap::new();
     let n = blocks.len();
     for i in 0..n {
         let (left, right) = blocks.split_at_mut(i + 1);
@@ -123,32 +191,28 @@ pub fn generate_cfg(function: &Function) -> CFG {
             }) => {
                 graph.add_edge(node, CFGNode::Return, Edge::Always);
             }
-            Some(Instruction::Effect {
-                op: EffectOps::Jump,
-                labels,
-                ..
-            }) => {
-                graph.add_edge(
-                    node,
-                    CFGNode::Block(*label_index.get(&labels[0]).unwrap()),
-                    Edge::Always,
-                );
+            Some(
+                instr @ Instruction::Effect {
+                    op: EffectOps::Jump,
+                    labels,
+                    ..
+                },
+            ) => {
+                let targets =
+                    resolve_targets(function, &label_index, instr, EffectOps::Jump, labels, 1)?;
+                graph.add_edge(node, targets[0], Edge::Always);
             }
-            Some(Instruction::Effect {
-                op: EffectOps::Branch,
-                labels,
-                ..
-            }) => {
-                graph.add_edge(
-                    node,
-                    CFGNode::Block(*label_index.get(&labels[0]).unwrap()),
-                    Edge::Bool(true),
-                );
-                graph.add_edge(
-                    node,
-                    CFGNode::Block(*label_index.get(&labels[1]).unwrap()),
-                    Edge::Bool(false),
-                );
+            Some(
+                instr @ Instruction::Effect {
+                    op: EffectOps::Branch,
+                    labels,
+                    ..
+                },
+            ) => {
+                let targets =
+                    resolve_targets(function, &label_index, instr, EffectOps::Branch, labels, 2)?;
+                graph.add_edge(node, targets[0], Edge::Bool(true));
+                graph.add_edge(node, targets[1], Edge::Bool(false));
             }
             _ => {
                 if let Some(next) = right.get(0) {
@@ -174,9 +238,19 @@ pub fn generate_cfg(function: &Function) -> CFG {
         };
     }
 
-    CFG {
+    Ok(graph)
+}
+
+pub fn generate_cfg(function: &Function) -> Result<CFG, CfgError> {
+    let mut blocks = form_blocks(function);
+    let graph = connect_blocks(&function.name, &mut blocks)?;
+
+    Ok(CFG {
+        name: function.name.clone(),
         blocks,
         graph,
         args: function.args.clone(),
-    }
+        return_type: function.return_type.clone(),
+        pos: function.pos.clone(),
+    })
 }
@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+
+use bril_rs::{Code, EffectOps, Function, Instruction};
+
+use crate::{CFGNode, CFG};
+
+impl CFG {
+    /// Lowers the CFG back into a function, dropping jumps to the next block in layout,
+    /// empty blocks with unused labels and the trailing return added by generate_cfg
+    pub fn to_function(&self) -> Function {
+        let order = self.layout();
+        let mut blocks: Vec<_> = order
+            .iter()
+            .map(|&i| (i, self.blocks[i].instrs.clone()))
+            .collect();
+
+        // Drop jumps that can fall through instead
+        for index in 0..blocks.len() {
+            let next_label = blocks
+                .get(index + 1)
+                .map(|(next, _)| &self.blocks[*next].label);
+            let instrs = &mut blocks[index].1;
+            if let Some(Instruction::Effect {
+                op: EffectOps::Jump,
+                labels,
+                ..
+            }) = instrs.last()
+            {
+                if Some(&labels[0]) == next_label {
+                    instrs.pop();
+                }
+            }
+        }
+
+        let used_labels: HashSet<_> = blocks
+            .iter()
+            .flat_map(|(_, instrs)| instrs.iter().flat_map(referenced_labels))
+            .collect();
+
+        let mut instrs = Vec::new();
+        for (i, block_instrs) in blocks {
+            let label = &self.blocks[i].label;
+            if used_labels.contains(label) {
+                instrs.push(Code::Label {
+                    label: label.clone(),
+                    pos: None,
+                });
+            }
+            instrs.extend(block_instrs.into_iter().map(Code::Instruction));
+        }
+
+        // Falling off the end of a function is an implicit return, but the return is kept
+        // after a jump or branch so the function never ends in one
+        if let [.., previous, Code::Instruction(Instruction::Effect {
+            op: EffectOps::Return,
+            args,
+            ..
+        })] = instrs.as_slice()
+        {
+            let falls_through = !matches!(
+                previous,
+                Code::Instruction(Instruction::Effect {
+                    op: EffectOps::Jump | EffectOps::Branch,
+                    ..
+                })
+            );
+            if args.is_empty() && falls_through {
+                instrs.pop();
+            }
+        }
+
+        Function {
+            name: self.name.clone(),
+            args: self.args.clone(),
+            instrs,
+            pos: self.pos.clone(),
+            return_type: self.return_type.clone(),
+        }
+    }
+
+    /// Orders blocks into chains so that every jump to a not yet placed block
+    /// is followed by its target, keeping the entry block first and preferring
+    /// to end the function with a block that returns
+    fn layout(&self) -> Vec<usize> {
+        let n = self.blocks.len();
+        let mut placed = vec![false; n];
+        let mut chains: Vec<Vec<usize>> = Vec::new();
+        for start in 0..n {
+            let mut chain = Vec::new();
+            let mut current = start;
+            while !placed[current] {
+                placed[current] = true;
+                chain.push(current);
+                match self.jump_target(current) {
+                    Some(next) => current = next,
+                    None => break,
+                }
+            }
+            if !chain.is_empty() {
+                chains.push(chain);
+            }
+        }
+
+        let return_rank = |chain: &Vec<usize>| {
+            let last = chain.last().and_then(|&i| self.blocks[i].instrs.last());
+            match last {
+                Some(Instruction::Effect {
+                    op: EffectOps::Return,
+                    args,
+                    ..
+                }) if args.is_empty() => 2,
+                Some(Instruction::Effect {
+                    op: EffectOps::Return,
+                    ..
+                }) => 1,
+                _ => 0,
+            }
+        };
+        if let Some((index, _)) = chains
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .max_by_key(|(_, chain)| return_rank(chain))
+        {
+            if return_rank(&chains[index]) > return_rank(&chains[chains.len() - 1]) {
+                let chain = chains.remove(index);
+                chains.push(chain);
+            }
+        }
+
+        chains.into_iter().flatten().collect()
+    }
+
+    /// Returns the block index targeted by the unconditional jump ending the given block
+    fn jump_target(&self, index: usize) -> Option<usize> {
+        match self.blocks[index].instrs.last() {
+            Some(Instruction::Effect {
+                op: EffectOps::Jump,
+                ..
+            }) => self
+                .graph
+                .neighbors(CFGNode::Block(index))
+                .find_map(|succ| match succ {
+                    CFGNode::Block(next) => Some(next),
+                    CFGNode::Return => None,
+                }),
+            _ => None,
+        }
+    }
+}
+
+/// Labels an instruction refers to, including the predecessor labels of phi nodes
+fn referenced_labels(instr: &Instruction) -> Vec<String> {
+    match instr {
+        Instruction::Value { labels, .. } | Instruction::Effect { labels, .. } => labels.clone(),
+        Instruction::Constant { .. } => Vec::new(),
+    }
+}
@@ -0,0 +1,18 @@
+use std::{io, process};
+
+use bril_rs::load_program;
+use cfg::{generate_cfg, Dot};
+
+fn main() -> io::Result<()> {
+    let program = load_program();
+    let mut dot = Dot::new();
+    for function in &program.functions {
+        let cfg = generate_cfg(function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        dot.add_cfg(&cfg, |_| Vec::new());
+    }
+    print!("{}", dot);
+    Ok(())
+}
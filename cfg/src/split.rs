@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use bbb::Block;
+use bril_rs::{EffectOps, Instruction, ValueOps};
+use petgraph::Direction::{Incoming, Outgoing};
+
+use crate::{CFGNode, Edge, CFG};
+
+impl CFG {
+    /// Returns edges whose source has several successors and whose target has several predecessors
+    pub fn critical_edges(&self) -> Vec<(CFGNode, CFGNode)> {
+        let mut edges: Vec<_> = self
+            .graph
+            .all_edges()
+            .map(|(from, to, _)| (from, to))
+            .filter(|&(from, to)| {
+                matches!(to, CFGNode::Block(_))
+                    && self.graph.neighbors_directed(from, Outgoing).count() > 1
+                    && self.graph.neighbors_directed(to, Incoming).count() > 1
+            })
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    /// Splits every critical edge with a fresh block, returns the inserted blocks
+    pub fn split_critical_edges(&mut self) -> Vec<CFGNode> {
+        self.critical_edges()
+            .into_iter()
+            .map(|(from, to)| self.split_edge(from, to))
+            .collect()
+    }
+
+    /// Inserts a fresh block on the edge from -> to that only jumps to to,
+    /// retargeting the terminator of from and the phi labels of to
+    pub fn split_edge(&mut self, from: CFGNode, to: CFGNode) -> CFGNode {
+        let edge = self
+            .graph
+            .remove_edge(from, to)
+            .expect("split edge should exist in the cfg");
+        let from_label = self.get_block(from).unwrap().label.clone();
+        let to_label = self.get_block(to).unwrap().label.clone();
+        let label = self.fresh_label(&format!("{}.{}", from_label, to_label));
+
+        let new_node = CFGNode::Block(self.blocks.len());
+        self.blocks.push(Block {
+            label: label.clone(),
+            instrs: vec![Instruction::Effect {
+                op: EffectOps::Jump,
+                labels: vec![to_label.clone()],
+                args: Vec::new(),
+                funcs: Vec::new(),
+                pos: None,
+            }],
+        });
+        self.graph.add_edge(from, new_node, edge);
+        self.graph.add_edge(new_node, to, Edge::Always);
+
+        if let Some(Instruction::Effect { labels, .. }) =
+            self.get_block_mut(from).unwrap().instrs.last_mut()
+        {
+            labels
+                .iter_mut()
+                .filter(|l| **l == to_label)
+                .for_each(|l| *l = label.clone());
+        }
+        for instr in &mut self.get_block_mut(to).unwrap().instrs {
+            if let Instruction::Value {
+                op: ValueOps::Phi,
+                labels,
+                ..
+            } = instr
+            {
+                labels
+                    .iter_mut()
+                    .filter(|l| **l == from_label)
+                    .for_each(|l| *l = label.clone());
+            }
+        }
+        new_node
+    }
+
+    /// Returns a label starting with base that no block uses yet
+    pub fn fresh_label(&self, base: &str) -> String {
+        let used: HashSet<_> = self.blocks.iter().map(|b| b.label.as_str()).collect();
+        if !used.contains(base) {
+            return base.to_string();
+        }
+        (0..)
+            .map(|i| format!("{}.{}", base, i))
+            .find(|label| !used.contains(label.as_str()))
+            .unwrap()
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+
+use bril_rs::{Function, Instruction, ValueOps};
+use callgraph::Summaries;
+use cfg::{generate_cfg, CFGNode, CfgError, CFG};
+use df::{
+    available_exprs::{AvailableExprs, Expression},
+    run_worklist_with, Analysis,
+};
+use itertools::Itertools;
+use util::SafeAccess;
+
+/// Where an expression is computed: block index, instruction index and destination
+type Site = (usize, usize, String);
+
+/// Returns the expression instr computes, including calls to pure functions
+fn expression(instr: &Instruction, summaries: &Summaries) -> Option<Expression> {
+    Expression::from_instr(instr)
+        .or_else(|| Expression::from_call(instr).filter(|_| summaries.is_pure_call(instr)))
+}
+
+/// Transfers available expressions across instr, making pure calls available too
+fn transfer(
+    fact: &AvailableExprs,
+    instr: &Instruction,
+    node: &CFGNode,
+    summaries: &Summaries,
+) -> AvailableExprs {
+    let mut next = fact.transfer_instr(instr, node);
+    if let (Some(expr), Some(dest)) = (Expression::from_call(instr), instr.get_dest()) {
+        if summaries.is_pure_call(instr) && !expr.uses(&dest) {
+            next.insert(expr);
+        }
+    }
+    next
+}
+
+/// Returns the available expressions before every instruction of node
+fn facts_before(
+    available: &HashMap<CFGNode, AvailableExprs>,
+    node: CFGNode,
+    cfg: &CFG,
+    summaries: &Summaries,
+) -> Vec<AvailableExprs> {
+    let mut fact = available[&node].clone();
+    let block = cfg.get_block(node).unwrap();
+    block
+        .instrs
+        .iter()
+        .map(|instr| {
+            let next = transfer(&fact, instr, &node, summaries);
+            std::mem::replace(&mut fact, next)
+        })
+        .collect()
+}
+
+/// Replaces computations of expressions already available with copies of a temporary holding
+/// them. The temporary is the destination of the computation that dominates the redundant ones
+/// when it is the only one and never reassigned, otherwise a fresh variable written after every
+/// computation that makes the expression available. Calls to functions summaries knows to be
+/// pure count as expressions. Returns whether anything was replaced
+pub fn global_cse(func: &mut Function, summaries: &Summaries) -> Result<bool, CfgError> {
+    let mut cfg = generate_cfg(func)?;
+    let available = run_worklist_with(&cfg, AvailableExprs::boundary(&cfg), |fact, node| {
+        let Some(block) = cfg.get_block(*node) else {
+            return fact.clone();
+        };
+        block.instrs.iter().fold(fact.clone(), |fact, instr| {
+            transfer(&fact, instr, node, summaries)
+        })
+    });
+
+    let mut sources: HashMap<Expression, Vec<Site>> = HashMap::new();
+    let mut redundant: HashMap<Expression, Vec<Site>> = HashMap::new();
+    for (b, block) in cfg.blocks.iter().enumerate() {
+        let node = CFGNode::Block(b);
+        if available.in_map.get(&node).is_none_or(|fact| fact.is_top()) {
+            continue;
+        }
+        let facts = facts_before(&available.in_map, node, &cfg, summaries);
+        for (i, (instr, before)) in block.instrs.iter().zip(facts).enumerate() {
+            let (Some(expr), Some(dest)) = (expression(instr, summaries), instr.get_dest()) else {
+                continue;
+            };
+            if before.contains(&expr) {
+                redundant.entry(expr).or_default().push((b, i, dest));
+            } else if !expr.uses(&dest) {
+                sources.entry(expr).or_default().push((b, i, dest));
+            }
+        }
+    }
+    if redundant.is_empty() {
+        return Ok(false);
+    }
+
+    let def_counts = cfg
+        .blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter().flat_map(|instr| instr.get_dest()))
+        .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+        .counts();
+    let mut used: HashSet<String> = def_counts.keys().cloned().collect();
+    let mut fresh = || {
+        let name = (0..)
+            .map(|i| format!("cse.{}", i))
+            .find(|name| !used.contains(name))
+            .unwrap();
+        used.insert(name.clone());
+        name
+    };
+
+    // Copies to insert after an instruction, and instructions to turn into copies
+    let mut inserts: HashMap<(usize, usize), Instruction> = HashMap::new();
+    let mut replaces: HashMap<(usize, usize), String> = HashMap::new();
+    for (expr, sites) in redundant
+        .into_iter()
+        .sorted_by_key(|(_, sites)| sites[0].clone())
+    {
+        let expr_sources = sources.remove(&expr).unwrap_or_default();
+        let temp = match expr_sources.as_slice() {
+            [(_, _, dest)] if def_counts[dest] == 1 => dest.clone(),
+            _ => {
+                let temp = fresh();
+                for (b, i, dest) in expr_sources {
+                    inserts.insert((b, i), copy(&temp, &dest, &expr));
+                }
+                temp
+            }
+        };
+        for (b, i, _) in sites {
+            replaces.insert((b, i), temp.clone());
+        }
+    }
+
+    for (b, block) in cfg.blocks.iter_mut().enumerate() {
+        let mut instrs = Vec::new();
+        for (i, instr) in block.instrs.drain(..).enumerate() {
+            match replaces.get(&(b, i)) {
+                Some(temp) => {
+                    let expr = expression(&instr, summaries).unwrap();
+                    instrs.push(copy(&instr.get_dest().unwrap(), temp, &expr));
+                }
+                None => instrs.push(instr),
+            }
+            instrs.extend(inserts.remove(&(b, i)));
+        }
+        block.instrs = instrs;
+    }
+    *func = cfg.to_function();
+    Ok(true)
+}
+
+fn copy(dest: &str, src: &str, expr: &Expression) -> Instruction {
+    Instruction::Value {
+        op: ValueOps::Id,
+        dest: dest.to_string(),
+        args: vec![src.to_string()],
+        funcs: Vec::new(),
+        labels: Vec::new(),
+        op_type: expr.kind().unwrap().clone(),
+        pos: None,
+    }
+}
@@ -0,0 +1,20 @@
+use std::{io, process};
+
+use bril_rs::{load_program_from_read, output_program};
+use callgraph::Summaries;
+use cse::global_cse;
+
+fn main() -> io::Result<()> {
+    let mut program = load_program_from_read(io::stdin());
+    let summaries = Summaries::new(&program);
+
+    for function in &mut program.functions {
+        global_cse(function, &summaries).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+    }
+
+    output_program(&program);
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::collections::{HashMap, HashSet};
+
+use bbb::{form_blocks, ToCode};
+use bril_rs::{EffectOps, Function, Instruction, ValueOps};
+use callgraph::Summaries;
+use cfg::{generate_cfg, CFGNode, CfgError};
+use df::{live_vars::LiveVars, run_worklist, Analysis};
+use util::SafeAccess;
+
+use crate::is_removable;
+
+/// Removes instructions whose destination is not live right after them,
+/// using live variables across the whole cfg. Returns whether anything was removed
+pub fn global_dce(func: &mut Function, summaries: &Summaries) -> Result<bool, CfgError> {
+    let mut dirty = false;
+    loop {
+        let mut cfg = generate_cfg(func)?;
+        let liveness = run_worklist::<LiveVars>(&cfg);
+        let mut changed = false;
+        for (i, block) in cfg.blocks.iter_mut().enumerate() {
+            let node = CFGNode::Block(i);
+            let mut live = liveness.out_map[&node].clone();
+            let mut kept = Vec::new();
+            for instr in block.instrs.iter().rev() {
+                let dead = instr.get_dest().is_none_or(|dest| !live.contains(&dest));
+                if dead && is_removable(instr, summaries) {
+                    changed = true;
+                    continue;
+                }
+                live = live.transfer_instr(instr, &node);
+                kept.push(instr.clone());
+            }
+            kept.reverse();
+            block.instrs = kept;
+        }
+        if !changed {
+            return Ok(dirty);
+        }
+        dirty = true;
+        *func = cfg.to_function();
+    }
+}
+
+/// Whether an instruction is kept regardless of its uses. Prints, stores, returns and calls
+/// to functions that are not pure are the roots, other effects except nop are kept too since
+/// control flow and frees are never removed
+fn is_root(instr: &Instruction, summaries: &Summaries) -> bool {
+    match instr {
+        Instruction::Effect {
+            op: EffectOps::Call,
+            ..
+        }
+        | Instruction::Value {
+            op: ValueOps::Call, ..
+        } => !summaries.is_pure_call(instr),
+        Instruction::Effect { op, .. } => *op != EffectOps::Nop,
+        Instruction::Value { .. } | Instruction::Constant { .. } => false,
+    }
+}
+
+/// Mark and sweep dce: keeps the roots and every definition they transitively read,
+/// removing the rest even if it is used by other unmarked instructions
+pub fn aggressive_dce(func: &mut Function, summaries: &Summaries) -> bool {
+    let mut blocks = form_blocks(func);
+    let mut defs: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+    for (b, block) in blocks.iter().enumerate() {
+        for (i, instr) in block.instrs.iter().enumerate() {
+            if let Some(dest) = instr.get_dest() {
+                defs.entry(dest).or_default().push((b, i));
+            }
+        }
+    }
+
+    let mut marked = HashSet::new();
+    let mut worklist = Vec::new();
+    for (b, block) in blocks.iter().enumerate() {
+        for (i, instr) in block.instrs.iter().enumerate() {
+            if is_root(instr, summaries) {
+                worklist.push((b, i));
+            }
+        }
+    }
+    while let Some((b, i)) = worklist.pop() {
+        if !marked.insert((b, i)) {
+            continue;
+        }
+        for arg in blocks[b].instrs[i].get_args().unwrap_or_default() {
+            worklist.extend(defs.get(&arg).into_iter().flatten().copied());
+        }
+    }
+
+    let mut dirty = false;
+    for (b, block) in blocks.iter_mut().enumerate() {
+        let original_length = block.instrs.len();
+        let mut i = 0;
+        block.instrs.retain(|_| {
+            i += 1;
+            marked.contains(&(b, i - 1))
+        });
+        dirty |= block.instrs.len() < original_length;
+    }
+    func.instrs = blocks.to_code();
+    dirty
+}
@@ -0,0 +1,100 @@
+use bbb::{form_blocks, Block, ToCode};
+use bril_rs::{EffectOps, Function, Instruction, ValueOps};
+use callgraph::Summaries;
+use std::collections::HashSet;
+use util::SafeAccess;
+
+pub use global::{aggressive_dce, global_dce};
+
+mod global;
+
+/// Whether instr does nothing besides writing its destination, so it can go once that is dead.
+/// Calls qualify when the callee is pure
+fn is_removable(instr: &Instruction, summaries: &Summaries) -> bool {
+    match instr {
+        Instruction::Value {
+            op: ValueOps::Call, ..
+        }
+        | Instruction::Effect {
+            op: EffectOps::Call,
+            ..
+        } => summaries.is_pure_call(instr),
+        Instruction::Value { .. } | Instruction::Constant { .. } => true,
+        Instruction::Effect { .. } => false,
+    }
+}
+
+fn find_used(blocks: &[Block]) -> HashSet<String> {
+    blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter().flat_map(|instr| instr.get_args()))
+        .flatten()
+        .collect()
+}
+
+fn trivial_dce_block(block: &mut Block, used: &HashSet<String>, summaries: &Summaries) -> bool {
+    let original_length = block.instrs.len();
+    block.instrs = block
+        .instrs
+        .iter()
+        .filter(|instr| {
+            let dead = instr.get_dest().is_none_or(|d| !used.contains(&d));
+            !(dead && is_removable(instr, summaries))
+        })
+        .cloned()
+        .collect();
+    block.instrs.len() < original_length
+}
+
+pub fn trivial_dce(func: &mut Function, summaries: &Summaries) -> bool {
+    let mut blocks = form_blocks(func);
+    let used = find_used(&blocks);
+    let mut dirty = false;
+    for block in &mut blocks {
+        dirty |= trivial_dce_block(block, &used, summaries);
+    }
+    func.instrs = blocks.to_code();
+    dirty
+}
+
+fn regular_dce_block(block: &mut Block, summaries: &Summaries) -> bool {
+    let mut dirty = false;
+    let mut dead_vars = HashSet::new();
+    let mut new_instrs = Vec::new();
+    for instr in block.instrs.iter().rev() {
+        if let Some(dest) = instr.get_dest() {
+            if dead_vars.contains(&dest) && is_removable(instr, summaries) {
+                dirty = true;
+                continue;
+            }
+            dead_vars.insert(dest);
+        }
+        if let Some(args) = instr.get_args() {
+            args.into_iter().for_each(|arg| {
+                dead_vars.remove(&arg);
+            });
+        }
+        new_instrs.push(instr.clone());
+    }
+    block.instrs = new_instrs.into_iter().rev().collect();
+    dirty
+}
+
+pub fn regular_dce(func: &mut Function, summaries: &Summaries) -> bool {
+    let mut blocks = form_blocks(func);
+    let mut dirty = false;
+    for block in &mut blocks {
+        dirty |= regular_dce_block(block, summaries);
+    }
+    func.instrs = blocks.to_code();
+    dirty
+}
+
+/// Repeats trivial and regular dce until convergence, returns whether anything was removed
+pub fn run_dce(func: &mut Function, summaries: &Summaries) -> bool {
+    let mut dirty = false;
+    while trivial_dce(func, summaries) || regular_dce(func, summaries) {
+        dirty = true;
+    }
+    dirty
+}
@@ -1,80 +1,43 @@
-use bbb::{form_blocks, Block, ToCode};
-use bril_rs::{load_program_from_read, output_program, Function};
-use std::{collections::HashSet, io};
-use util::SafeAccess;
+use bril_rs::{load_program_from_read, output_program};
+use callgraph::Summaries;
+use dce::{aggressive_dce, global_dce, regular_dce, trivial_dce};
+use std::{env, io, process};
 
-fn find_used(blocks: &Vec<Block>) -> HashSet<String> {
-    blocks
-        .iter()
-        .flat_map(|block| block.instrs.iter().flat_map(|instr| instr.get_args()))
-        .flatten()
-        .collect()
-}
-
-fn trivial_dce_block(block: &mut Block, used: &HashSet<String>) -> bool {
-    let original_length = block.instrs.len();
-    block.instrs = block
-        .instrs
-        .iter()
-        .filter(|instr| instr.get_dest().map_or(true, |d| used.contains(&d)))
-        .cloned()
-        .collect();
-    block.instrs.len() < original_length
-}
-
-fn trivial_dce(func: &mut Function) -> bool {
-    let mut blocks = form_blocks(func);
-    let used = find_used(&blocks);
-    let mut dirty = false;
-    for block in &mut blocks {
-        dirty |= trivial_dce_block(block, &used);
-    }
-    func.instrs = blocks.to_code();
-    dirty
-}
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    // Valid values: ["local", "global", "aggressive"], defaults to "local" if empty
+    let mode = args.get(1).map(|a| a.as_str()).unwrap_or("local");
 
-fn regular_dce_block(block: &mut Block) -> bool {
-    let mut dirty = false;
-    let mut dead_vars = HashSet::new();
-    let mut new_instrs = Vec::new();
-    for instr in block.instrs.iter().rev() {
-        if let Some(dest) = instr.get_dest() {
-            if dead_vars.contains(&dest) {
-                dirty = true;
-                continue;
+    let mut program = load_program_from_read(io::stdin());
+    let summaries = Summaries::new(&program);
+
+    match mode {
+        "local" => {
+            // Repeat dce passes until convergence
+            while program
+                .functions
+                .iter_mut()
+                .any(|f| trivial_dce(f, &summaries) || regular_dce(f, &summaries))
+            {}
+        }
+        "global" => {
+            for function in &mut program.functions {
+                global_dce(function, &summaries).unwrap_or_else(|e| {
+                    eprintln!("error: {}", e);
+                    process::exit(1);
+                });
             }
-            dead_vars.insert(dest);
         }
-        if let Some(args) = instr.get_args() {
-            args.into_iter().for_each(|arg| {
-                dead_vars.remove(&arg);
-            });
+        "aggressive" => {
+            for function in &mut program.functions {
+                aggressive_dce(function, &summaries);
+            }
+        }
+        _ => {
+            eprintln!("Unknown mode: {}", mode);
+            process::exit(1);
         }
-        new_instrs.push(instr.clone());
-    }
-    block.instrs = new_instrs.into_iter().rev().collect();
-    dirty
-}
-
-fn regular_dce(func: &mut Function) -> bool {
-    let mut blocks = form_blocks(func);
-    let mut dirty = false;
-    for block in &mut blocks {
-        dirty |= regular_dce_block(block);
     }
-    func.instrs = blocks.to_code();
-    dirty
-}
-
-fn main() -> io::Result<()> {
-    let mut program = load_program_from_read(io::stdin());
-
-    // Repeat dce passes until convergence
-    while program
-        .functions
-        .iter_mut()
-        .any(|f| trivial_dce(f) || regular_dce(f))
-    {}
 
     output_program(&program);
     Ok(())
@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use crate::{Analysis, DataFlowDisplay, Direction};
+use bril_rs::Instruction;
+use cfg::{CFGNode, CFG};
+use itertools::Itertools;
+use serde_json::json;
+use util::value::Value;
+
+/// A pure computation over variables, with the arguments of commutative operations sorted
+pub type Expression = Value<String>;
+
+/// Expressions computed on every path to a point with no argument redefined since.
+/// None stands for every expression, the fact of nodes not reached yet
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AvailableExprs(Option<HashSet<Expression>>);
+
+impl AvailableExprs {
+    pub fn contains(&self, expr: &Expression) -> bool {
+        self.0.as_ref().is_none_or(|exprs| exprs.contains(expr))
+    }
+
+    /// Whether this is the fact of a node no path from the entry has reached
+    pub fn is_top(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Makes expr available, for expressions from_instr does not know are pure
+    pub fn insert(&mut self, expr: Expression) {
+        if let Some(exprs) = &mut self.0 {
+            exprs.insert(expr);
+        }
+    }
+
+    /// Returns the available expressions, empty for the fact holding every expression
+    pub fn iter(&self) -> impl Iterator<Item = &Expression> {
+        self.0.iter().flatten()
+    }
+}
+
+impl Analysis for AvailableExprs {
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        AvailableExprs(None)
+    }
+
+    fn bottom(_cfg: &CFG) -> Self {
+        AvailableExprs(Some(HashSet::new()))
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (None, _) => other.clone(),
+            (_, None) => self.clone(),
+            (Some(a), Some(b)) => AvailableExprs(Some(a.intersection(b).cloned().collect())),
+        }
+    }
+
+    fn boundary(cfg: &CFG) -> Self {
+        Self::bottom(cfg)
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let Some(exprs) = &self.0 else {
+            return self.clone();
+        };
+        let mut exprs = exprs.clone();
+        let dest = match instr {
+            Instruction::Value { dest, .. } | Instruction::Constant { dest, .. } => dest,
+            Instruction::Effect { .. } => return AvailableExprs(Some(exprs)),
+        };
+        exprs.retain(|expr| !expr.uses(dest));
+        if let Some(expr) = Expression::from_instr(instr) {
+            // x = add x y computes an expression over the old x, which is gone afterwards
+            if !expr.uses(dest) {
+                exprs.insert(expr);
+            }
+        }
+        AvailableExprs(Some(exprs))
+    }
+}
+
+impl DataFlowDisplay for AvailableExprs {
+    fn generate_string(&self, _cfg: &CFG) -> String {
+        match &self.0 {
+            None => "⊤".to_string(),
+            Some(exprs) if exprs.is_empty() => "∅".to_string(),
+            Some(exprs) => exprs
+                .iter()
+                .map(|expr| format!("({})", expr))
+                .sorted()
+                .join(" "),
+        }
+    }
+
+    /// Null for the set of every expression
+    fn to_json(&self, _cfg: &CFG) -> serde_json::Value {
+        match &self.0 {
+            None => serde_json::Value::Null,
+            Some(exprs) => json!(exprs
+                .iter()
+                .map(|expr| expr.to_string())
+                .sorted()
+                .collect::<Vec<_>>()),
+        }
+    }
+}
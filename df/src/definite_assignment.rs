@@ -0,0 +1,172 @@
+use std::{collections::HashSet, fmt, ops::Deref};
+
+use crate::{run_worklist, Analysis, DataFlowDisplay, Direction};
+use bril_rs::{Instruction, Position, ValueOps};
+use cfg::{CFGNode, CFG};
+use itertools::Itertools;
+use serde_json::{json, Value};
+use util::SafeAccess;
+
+/// Variables assigned on every path from the entry
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct DefinitelyAssigned(HashSet<String>);
+
+impl Deref for DefinitelyAssigned {
+    type Target = HashSet<String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Analysis for DefinitelyAssigned {
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn top(cfg: &CFG) -> Self {
+        let vars = cfg
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instrs)
+            .flat_map(|instr| {
+                instr
+                    .get_args()
+                    .into_iter()
+                    .flatten()
+                    .chain(instr.get_dest())
+            })
+            .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+            .collect();
+        DefinitelyAssigned(vars)
+    }
+
+    fn bottom(_cfg: &CFG) -> Self {
+        DefinitelyAssigned(HashSet::new())
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        DefinitelyAssigned(self.intersection(other).cloned().collect())
+    }
+
+    fn boundary(cfg: &CFG) -> Self {
+        DefinitelyAssigned(cfg.args.iter().map(|arg| arg.name.clone()).collect())
+    }
+
+    /// A phi copies the argument of the edge it is entered from, which may itself be unassigned
+    fn transfer_edge(&self, from: CFGNode, to: CFGNode, cfg: &CFG) -> Self {
+        let (Some(from_block), Some(to_block)) = (cfg.get_block(from), cfg.get_block(to)) else {
+            return self.clone();
+        };
+        let mut assigned = self.0.clone();
+        for instr in &to_block.instrs {
+            if let Instruction::Value {
+                op: ValueOps::Phi,
+                dest,
+                args,
+                labels,
+                ..
+            } = instr
+            {
+                let incoming = labels
+                    .iter()
+                    .position(|label| *label == from_block.label)
+                    .map(|i| &args[i]);
+                if incoming.is_some_and(|arg| self.contains(arg)) {
+                    assigned.insert(dest.clone());
+                } else {
+                    assigned.remove(dest);
+                }
+            }
+        }
+        DefinitelyAssigned(assigned)
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut assigned = self.0.clone();
+        match instr {
+            Instruction::Value {
+                op: ValueOps::Phi, ..
+            } => {}
+            _ => assigned.extend(instr.get_dest()),
+        }
+        DefinitelyAssigned(assigned)
+    }
+}
+
+impl DataFlowDisplay for DefinitelyAssigned {
+    fn generate_string(&self, _cfg: &CFG) -> String {
+        if self.is_empty() {
+            return "∅".to_string();
+        }
+
+        self.iter().sorted().cloned().collect::<Vec<_>>().join(" ")
+    }
+
+    fn to_json(&self, _cfg: &CFG) -> Value {
+        json!(self.iter().sorted().collect::<Vec<_>>())
+    }
+}
+
+/// A read of a variable that is unassigned on some path reaching it
+#[derive(Clone, PartialEq, Debug)]
+pub struct UninitializedUse {
+    pub function: String,
+    pub var: String,
+    pub instr: Instruction,
+    pub pos: Option<Position>,
+}
+
+impl fmt::Display for UninitializedUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}: ", self.function)?;
+        if let Some(pos) = &self.pos {
+            write!(f, "{}:{}: ", pos.pos.row, pos.pos.col)?;
+        }
+        write!(
+            f,
+            "`{}` may be used before it is assigned in `{}`",
+            self.var, self.instr
+        )
+    }
+}
+
+impl UninitializedUse {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "function": self.function,
+            "var": self.var,
+            "instr": self.instr.to_string(),
+            "pos": self.pos.as_ref().map(|pos| json!({ "row": pos.pos.row, "col": pos.pos.col })),
+        })
+    }
+}
+
+/// Returns every argument read while possibly unassigned, in program order.
+/// Phi arguments are not reported since a phi only passes unassigned values along
+pub fn uninitialized_uses(cfg: &CFG) -> Vec<UninitializedUse> {
+    let result = run_worklist::<DefinitelyAssigned>(cfg);
+    let mut uses = Vec::new();
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        let facts = result.instr_facts(CFGNode::Block(i), cfg);
+        for (instr, (before, _)) in block.instrs.iter().zip(facts) {
+            if let Instruction::Value {
+                op: ValueOps::Phi, ..
+            } = instr
+            {
+                continue;
+            }
+            for var in instr.get_args().unwrap_or_default().into_iter().unique() {
+                if !before.contains(&var) {
+                    uses.push(UninitializedUse {
+                        function: cfg.name.clone(),
+                        var,
+                        instr: instr.clone(),
+                        pos: instr.get_pos(),
+                    });
+                }
+            }
+        }
+    }
+    uses
+}
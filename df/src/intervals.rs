@@ -0,0 +1,441 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{Analysis, DataFlowDisplay, Direction};
+use bril_rs::{EffectOps, Instruction, Literal, Type, ValueOps};
+use cfg::{CFGNode, Edge, CFG};
+use itertools::Itertools;
+use serde_json::{json, Map, Value};
+use util::SafeAccess;
+
+/// Inclusive range of an int variable, i64::MIN and i64::MAX stand for infinite bounds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Interval {
+    pub const FULL: Interval = Interval {
+        lo: i64::MIN,
+        hi: i64::MAX,
+    };
+
+    pub fn constant(c: i64) -> Self {
+        Interval { lo: c, hi: c }
+    }
+
+    pub fn contains(&self, c: i64) -> bool {
+        self.lo <= c && c <= self.hi
+    }
+
+    pub fn hull(&self, other: &Self) -> Self {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    /// Returns the common part of both ranges, None if they are disjoint
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let result = Interval {
+            lo: self.lo.max(other.lo),
+            hi: self.hi.min(other.hi),
+        };
+        (result.lo <= result.hi).then_some(result)
+    }
+
+    /// Keeps the bounds of previous, sending the ones next grew past to infinity
+    pub fn widen(&self, next: &Self) -> Self {
+        Interval {
+            lo: if next.lo < self.lo { i64::MIN } else { self.lo },
+            hi: if next.hi > self.hi { i64::MAX } else { self.hi },
+        }
+    }
+
+    fn is_finite(&self) -> bool {
+        self.lo != i64::MIN && self.hi != i64::MAX
+    }
+
+    /// Builds the range spanned by the given bounds, the full range if any of them
+    /// overflowed since bril arithmetic wraps around
+    fn from_bounds(bounds: &[Option<i64>]) -> Self {
+        let Some(bounds) = bounds.iter().copied().collect::<Option<Vec<_>>>() else {
+            return Interval::FULL;
+        };
+        Interval {
+            lo: *bounds.iter().min().unwrap(),
+            hi: *bounds.iter().max().unwrap(),
+        }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        if !self.is_finite() || !other.is_finite() {
+            return Interval::FULL;
+        }
+        Interval::from_bounds(&[self.lo.checked_add(other.lo), self.hi.checked_add(other.hi)])
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        if !self.is_finite() || !other.is_finite() {
+            return Interval::FULL;
+        }
+        Interval::from_bounds(&[self.lo.checked_sub(other.hi), self.hi.checked_sub(other.lo)])
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        if !self.is_finite() || !other.is_finite() {
+            return Interval::FULL;
+        }
+        Interval::from_bounds(&[
+            self.lo.checked_mul(other.lo),
+            self.lo.checked_mul(other.hi),
+            self.hi.checked_mul(other.lo),
+            self.hi.checked_mul(other.hi),
+        ])
+    }
+
+    /// Division by zero stops the program, so only the nonzero divisors are considered
+    fn div(&self, other: &Self) -> Self {
+        if !self.is_finite() {
+            return Interval::FULL;
+        }
+        let parts = [
+            other.intersect(&Interval {
+                lo: i64::MIN,
+                hi: -1,
+            }),
+            other.intersect(&Interval {
+                lo: 1,
+                hi: i64::MAX,
+            }),
+        ];
+        parts
+            .into_iter()
+            .flatten()
+            .map(|divisor| {
+                // Dividing by an unbounded divisor can reach every value down to zero
+                let divisor = Interval {
+                    lo: if divisor.lo == i64::MIN {
+                        -1
+                    } else {
+                        divisor.lo
+                    },
+                    hi: if divisor.hi == i64::MAX {
+                        1
+                    } else {
+                        divisor.hi
+                    },
+                };
+                let lo = divisor.lo.min(divisor.hi);
+                let hi = divisor.lo.max(divisor.hi);
+                let mut bounds = vec![
+                    self.lo.checked_div(lo),
+                    self.lo.checked_div(hi),
+                    self.hi.checked_div(lo),
+                    self.hi.checked_div(hi),
+                ];
+                if other.lo == i64::MIN || other.hi == i64::MAX {
+                    bounds.push(Some(0));
+                }
+                Interval::from_bounds(&bounds)
+            })
+            .reduce(|a, b| a.hull(&b))
+            .unwrap_or(Interval::FULL)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lo = match self.lo {
+            i64::MIN => "-inf".to_string(),
+            lo => lo.to_string(),
+        };
+        let hi = match self.hi {
+            i64::MAX => "+inf".to_string(),
+            hi => hi.to_string(),
+        };
+        write!(f, "[{}, {}]", lo, hi)
+    }
+}
+
+/// Range of every int variable at a point, variables without an entry can hold any value.
+/// None means the point cannot be reached
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Intervals(Option<HashMap<String, Interval>>);
+
+impl Intervals {
+    pub fn is_reachable(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn range(&self, var: &str) -> Interval {
+        self.0
+            .as_ref()
+            .and_then(|ranges| ranges.get(var))
+            .copied()
+            .unwrap_or(Interval::FULL)
+    }
+
+    fn set(&mut self, var: &str, interval: Interval) {
+        if let Some(ranges) = &mut self.0 {
+            if interval == Interval::FULL {
+                ranges.remove(var);
+            } else {
+                ranges.insert(var.to_string(), interval);
+            }
+        }
+    }
+
+    fn evaluate(&self, op: ValueOps, args: &[String]) -> Interval {
+        let range = |i: usize| self.range(&args[i]);
+        match op {
+            ValueOps::Id => range(0),
+            ValueOps::Add => range(0).add(&range(1)),
+            ValueOps::Sub => range(0).sub(&range(1)),
+            ValueOps::Mul => range(0).mul(&range(1)),
+            ValueOps::Div => range(0).div(&range(1)),
+            ValueOps::Phi => args
+                .iter()
+                .map(|arg| self.range(arg))
+                .reduce(|a, b| a.hull(&b))
+                .unwrap_or(Interval::FULL),
+            _ => Interval::FULL,
+        }
+    }
+
+    /// Narrows the ranges of a and b assuming a op b evaluated to taken,
+    /// returns false if that cannot happen
+    fn refine(&mut self, op: ValueOps, a: &str, b: &str, taken: bool) -> bool {
+        let (x, y) = (self.range(a), self.range(b));
+        // Negating a comparison swaps it for its complement
+        let op = match (op, taken) {
+            (op, true) => op,
+            (ValueOps::Lt, false) => ValueOps::Ge,
+            (ValueOps::Le, false) => ValueOps::Gt,
+            (ValueOps::Gt, false) => ValueOps::Le,
+            (ValueOps::Ge, false) => ValueOps::Lt,
+            (ValueOps::Eq, false) => {
+                // Only a singleton at the edge of the other range can be cut off
+                let trim = |r: Interval, c: Interval| {
+                    if c.lo != c.hi {
+                        Some(r)
+                    } else if r.lo == c.lo && r.hi == c.lo {
+                        None
+                    } else if r.lo == c.lo {
+                        Some(Interval { lo: r.lo + 1, ..r })
+                    } else if r.hi == c.lo {
+                        Some(Interval { hi: r.hi - 1, ..r })
+                    } else {
+                        Some(r)
+                    }
+                };
+                return match (trim(x, y), trim(y, x)) {
+                    (Some(x), Some(y)) => {
+                        self.set(a, x);
+                        self.set(b, y);
+                        true
+                    }
+                    _ => false,
+                };
+            }
+            _ => return true,
+        };
+        let below = |r: Interval, bound: i64| {
+            r.intersect(&Interval {
+                lo: i64::MIN,
+                hi: bound,
+            })
+        };
+        let above = |r: Interval, bound: i64| {
+            r.intersect(&Interval {
+                lo: bound,
+                hi: i64::MAX,
+            })
+        };
+        let refined = match op {
+            ValueOps::Lt => below(x, dec(y.hi)).zip(above(y, inc(x.lo))),
+            ValueOps::Le => below(x, y.hi).zip(above(y, x.lo)),
+            ValueOps::Gt => above(x, inc(y.lo)).zip(below(y, dec(x.hi))),
+            ValueOps::Ge => above(x, y.lo).zip(below(y, x.hi)),
+            ValueOps::Eq => x.intersect(&y).map(|r| (r, r)),
+            _ => Some((x, y)),
+        };
+        match refined {
+            Some((x, y)) => {
+                self.set(a, x);
+                self.set(b, y);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Steps a bound by one, leaving infinite bounds alone
+fn inc(bound: i64) -> i64 {
+    match bound {
+        i64::MIN | i64::MAX => bound,
+        _ => bound + 1,
+    }
+}
+
+fn dec(bound: i64) -> i64 {
+    match bound {
+        i64::MIN | i64::MAX => bound,
+        _ => bound - 1,
+    }
+}
+
+/// Finds the comparison a branch at the end of block tests, if its arguments still hold the
+/// compared values when the branch runs
+fn branch_comparison(instrs: &[Instruction]) -> Option<(ValueOps, String, String)> {
+    let Some(Instruction::Effect {
+        op: EffectOps::Branch,
+        args: cond,
+        ..
+    }) = instrs.last()
+    else {
+        return None;
+    };
+    let mut redefined = HashSet::new();
+    for instr in instrs.iter().rev().skip(1) {
+        if let Instruction::Value {
+            op: op @ (ValueOps::Lt | ValueOps::Le | ValueOps::Gt | ValueOps::Ge | ValueOps::Eq),
+            dest,
+            args,
+            ..
+        } = instr
+        {
+            if *dest == cond[0] {
+                let intact = args.iter().all(|arg| !redefined.contains(arg));
+                return intact.then(|| (*op, args[0].clone(), args[1].clone()));
+            }
+        }
+        if let Some(dest) = instr.get_dest() {
+            if dest == cond[0] {
+                return None;
+            }
+            redefined.insert(dest);
+        }
+    }
+    None
+}
+
+impl Analysis for Intervals {
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        Intervals(None)
+    }
+
+    fn bottom(_cfg: &CFG) -> Self {
+        Intervals(Some(HashMap::new()))
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (None, _) => other.clone(),
+            (_, None) => self.clone(),
+            (Some(a), Some(b)) => Intervals(Some(
+                a.iter()
+                    .filter_map(|(var, x)| b.get(var).map(|y| (var.clone(), x.hull(y))))
+                    .collect(),
+            )),
+        }
+    }
+
+    fn widen(&self, next: &Self) -> Self {
+        let (Some(previous), Some(ranges)) = (&self.0, &next.0) else {
+            return next.clone();
+        };
+        Intervals(Some(
+            ranges
+                .iter()
+                .filter_map(|(var, r)| {
+                    let widened = previous.get(var)?.widen(r);
+                    (widened != Interval::FULL).then(|| (var.clone(), widened))
+                })
+                .collect(),
+        ))
+    }
+
+    fn boundary(cfg: &CFG) -> Self {
+        Self::bottom(cfg)
+    }
+
+    fn transfer_edge(&self, from: CFGNode, to: CFGNode, cfg: &CFG) -> Self {
+        let (Some(Edge::Bool(taken)), Some(block)) =
+            (cfg.graph.edge_weight(from, to), cfg.get_block(from))
+        else {
+            return self.clone();
+        };
+        let Some((op, a, b)) = branch_comparison(&block.instrs) else {
+            return self.clone();
+        };
+        let mut refined = self.clone();
+        if refined.is_reachable() && !refined.refine(op, &a, &b, *taken) {
+            return Intervals(None);
+        }
+        refined
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut fact = self.clone();
+        match instr {
+            Instruction::Constant {
+                dest,
+                value: Literal::Int(c),
+                ..
+            } => fact.set(dest, Interval::constant(*c)),
+            Instruction::Value {
+                dest,
+                op,
+                args,
+                op_type: Type::Int,
+                ..
+            } => fact.set(dest, self.evaluate(*op, args)),
+            _ => {
+                if let Some(dest) = instr.get_dest() {
+                    fact.set(&dest, Interval::FULL);
+                }
+            }
+        }
+        fact
+    }
+}
+
+impl DataFlowDisplay for Intervals {
+    fn generate_string(&self, _cfg: &CFG) -> String {
+        match &self.0 {
+            None => "unreachable".to_string(),
+            Some(ranges) if ranges.is_empty() => "∅".to_string(),
+            Some(ranges) => ranges
+                .iter()
+                .sorted_by_key(|(var, _)| var.as_str())
+                .map(|(var, r)| format!("{}: {}", var, r))
+                .join(", "),
+        }
+    }
+
+    /// Null when unreachable, otherwise the bounds of every variable with null for infinity
+    fn to_json(&self, _cfg: &CFG) -> Value {
+        let Some(ranges) = &self.0 else {
+            return Value::Null;
+        };
+        let bound = |b: i64| match b {
+            i64::MIN | i64::MAX => Value::Null,
+            b => json!(b),
+        };
+        let ranges: Map<_, _> = ranges
+            .iter()
+            .sorted_by_key(|(var, _)| var.as_str())
+            .map(|(var, r)| (var.clone(), json!({ "lo": bound(r.lo), "hi": bound(r.hi) })))
+            .collect();
+        Value::Object(ranges)
+    }
+}
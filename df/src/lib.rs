@@ -1,18 +1,73 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use cfg::{CFGNode, CFG};
-use petgraph::EdgeDirection::{self, Incoming, Outgoing};
+use bril_rs::Instruction;
+use cfg::{CFGNode, Dot, CFG};
+use petgraph::{
+    visit::{DfsPostOrder, Walker},
+    EdgeDirection::{self, Incoming, Outgoing},
+};
+use serde_json::{json, Value};
 
-pub trait Analysis: Default + Clone + PartialEq + DataFlowDisplay {
+pub mod available_exprs;
+pub mod definite_assignment;
+pub mod intervals;
+pub mod live_vars;
+pub mod reaching_defs;
+
+/// A fact of a dataflow analysis, an element of a lattice ordered so that meet only goes down
+pub trait Analysis: Clone + PartialEq + DataFlowDisplay {
     fn direction() -> Direction;
 
+    /// Identity of meet, the fact of nodes no path has reached yet
+    fn top(cfg: &CFG) -> Self;
+
+    /// Absorbing element of meet, the fact that assumes nothing
+    fn bottom(cfg: &CFG) -> Self;
+
+    /// Combines the facts flowing into a node from several neighbors
     fn meet(&self, other: &Self) -> Self;
 
-    fn transfer(&self, node: &CFGNode, cfg: &CFG) -> Self;
+    /// Jumps from the previous fact of a loop head to one below next, so that lattices of
+    /// infinite height still reach a fixpoint. Defaults to next for finite lattices
+    fn widen(&self, next: &Self) -> Self {
+        next.clone()
+    }
+
+    /// Fact at the start of the analysis: entering Block(0) going forward,
+    /// or leaving through CFGNode::Return going backward. Defaults to top
+    fn boundary(cfg: &CFG) -> Self {
+        Self::top(cfg)
+    }
+
+    /// Refines the fact leaving from along the cfg edge to to, in graph direction.
+    /// Defaults to the fact unchanged
+    fn transfer_edge(&self, _from: CFGNode, _to: CFGNode, _cfg: &CFG) -> Self {
+        self.clone()
+    }
+
+    /// Transfers the fact across one instruction of node, in the direction of the analysis
+    fn transfer_instr(&self, instr: &Instruction, node: &CFGNode) -> Self;
+
+    /// Transfers the fact across a whole node, one instruction at a time
+    fn transfer(&self, node: &CFGNode, cfg: &CFG) -> Self {
+        let Some(block) = cfg.get_block(*node) else {
+            return self.clone();
+        };
+        let fold = |fact: Self, instr| fact.transfer_instr(instr, node);
+        match Self::direction() {
+            Direction::Forward => block.instrs.iter().fold(self.clone(), fold),
+            Direction::Backward => block.instrs.iter().rev().fold(self.clone(), fold),
+        }
+    }
 }
 
 pub trait DataFlowDisplay {
     fn generate_string(&self, cfg: &CFG) -> String;
+
+    /// The fact as structured data. Defaults to the text of generate_string
+    fn to_json(&self, cfg: &CFG) -> Value {
+        Value::String(self.generate_string(cfg))
+    }
 }
 
 pub enum Direction {
@@ -34,29 +89,193 @@ pub struct DataFlowResult<T: DataFlowDisplay> {
     pub out_map: HashMap<CFGNode, T>,
 }
 
-/// Returns mapping from CFGNode to its reaching definitions
+impl<T: Analysis> DataFlowResult<T> {
+    /// Returns the facts holding before and after every instruction of node, in program order
+    pub fn instr_facts(&self, node: CFGNode, cfg: &CFG) -> Vec<(T, T)> {
+        let Some(block) = cfg.get_block(node) else {
+            return Vec::new();
+        };
+        match T::direction() {
+            Direction::Forward => {
+                let mut fact = self
+                    .in_map
+                    .get(&node)
+                    .cloned()
+                    .unwrap_or_else(|| T::top(cfg));
+                block
+                    .instrs
+                    .iter()
+                    .map(|instr| {
+                        let after = fact.transfer_instr(instr, &node);
+                        (std::mem::replace(&mut fact, after.clone()), after)
+                    })
+                    .collect()
+            }
+            Direction::Backward => {
+                let mut fact = self
+                    .out_map
+                    .get(&node)
+                    .cloned()
+                    .unwrap_or_else(|| T::top(cfg));
+                let mut facts: Vec<_> = block
+                    .instrs
+                    .iter()
+                    .rev()
+                    .map(|instr| {
+                        let before = fact.transfer_instr(instr, &node);
+                        (before.clone(), std::mem::replace(&mut fact, before))
+                    })
+                    .collect();
+                facts.reverse();
+                facts
+            }
+        }
+    }
+
+    /// Returns the facts of every block of cfg, and of every instruction within it
+    pub fn to_json(&self, cfg: &CFG) -> Value {
+        let format = |f: Option<&T>| f.map_or(Value::Null, |fact| fact.to_json(cfg));
+        let blocks: Vec<_> = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| {
+                let node = CFGNode::Block(i);
+                let instrs: Vec<_> = block
+                    .instrs
+                    .iter()
+                    .zip(self.instr_facts(node, cfg))
+                    .map(|(instr, (before, after))| {
+                        json!({
+                            "instr": instr.to_string(),
+                            "before": before.to_json(cfg),
+                            "after": after.to_json(cfg),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "label": block.label,
+                    "in": format(self.in_map.get(&node)),
+                    "out": format(self.out_map.get(&node)),
+                    "instrs": instrs,
+                })
+            })
+            .collect();
+        json!({ "function": cfg.name, "blocks": blocks })
+    }
+}
+
+impl<T: DataFlowDisplay> DataFlowResult<T> {
+    /// Adds cfg to dot with the in and out facts shown under each block
+    pub fn add_to_dot(&self, cfg: &CFG, dot: &mut Dot) {
+        let format = |f: Option<&T>| f.map_or("N/A".to_string(), |set| set.generate_string(cfg));
+        dot.add_cfg(cfg, |node| {
+            vec![
+                format!("in: {}", format(self.in_map.get(&node))),
+                format!("out: {}", format(self.out_map.get(&node))),
+            ]
+        });
+    }
+}
+
+/// Passes over the whole cfg made after widening reached a fixpoint
+const NARROWING_SWEEPS: usize = 2;
+
+/// Returns the facts entering and leaving every CFGNode, in program order.
+/// Backward analyses are solved from CFGNode::Return, then their maps are swapped
+/// so in_map always holds the fact before a node and out_map the fact after it
 pub fn run_worklist<T: Analysis>(cfg: &CFG) -> DataFlowResult<T> {
-    let mut in_map = HashMap::new();
-    let mut out_map = HashMap::new();
+    run_worklist_with(cfg, T::boundary(cfg), |fact, node| fact.transfer(node, cfg))
+}
 
-    let mut worklist: VecDeque<_> = cfg.graph.nodes().collect();
+/// Like run_worklist, but starts from the given boundary fact and transfers nodes with transfer,
+/// for analyses whose transfer depends on the results of earlier analyses
+pub fn run_worklist_with<T: Analysis>(
+    cfg: &CFG,
+    boundary: T,
+    transfer: impl Fn(&T, &CFGNode) -> T,
+) -> DataFlowResult<T> {
+    let mut in_map: HashMap<CFGNode, T> = HashMap::new();
+    let mut out_map: HashMap<CFGNode, T> = HashMap::new();
 
+    let (boundary_node, order) = match T::direction() {
+        Direction::Forward => (CFGNode::Block(0), reverse_postorder(cfg)),
+        Direction::Backward => {
+            let mut order = reverse_postorder(cfg);
+            order.reverse();
+            (CFGNode::Return, order)
+        }
+    };
     let graph_direction = T::direction().into();
+    let index: HashMap<_, _> = order.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+    // Nodes entered from a node later in the order head a loop and get widened
+    let loop_heads: HashSet<_> = order
+        .iter()
+        .filter(|node| {
+            cfg.graph
+                .neighbors_directed(**node, graph_direction)
+                .any(|pred| index[&pred] >= index[node])
+        })
+        .copied()
+        .collect();
 
-    while let Some(node) = worklist.pop_front() {
-        let in_set = cfg
-            .graph
+    let meet_preds = |node: CFGNode, out_map: &HashMap<CFGNode, T>| {
+        if node == boundary_node {
+            return boundary.clone();
+        }
+        cfg.graph
             .neighbors_directed(node, graph_direction)
-            .into_iter()
-            .flat_map(|p| out_map.get(&p))
-            .fold(T::default(), |acc, next| acc.meet(next));
-        in_map.insert(node, in_set.clone());
-        let out_set = in_set.transfer(&node, &cfg);
-        let old_out = out_map.insert(node, out_set.clone());
-        if old_out != Some(out_set) {
-            cfg.graph
-                .neighbors_directed(node, graph_direction.opposite())
-                .for_each(|s| worklist.push_back(s));
+            .flat_map(|p| {
+                let (from, to) = match graph_direction {
+                    Incoming => (p, node),
+                    Outgoing => (node, p),
+                };
+                out_map
+                    .get(&p)
+                    .map(|fact| fact.transfer_edge(from, to, cfg))
+            })
+            .fold(T::top(cfg), |acc, next| acc.meet(&next))
+    };
+
+    // Always visits the pending node that comes first in the order
+    let mut worklist: BTreeSet<usize> = (0..order.len()).collect();
+    let mut widened = false;
+    while let Some(i) = worklist.pop_first() {
+        let node = order[i];
+        let mut in_set = meet_preds(node, &out_map);
+        if loop_heads.contains(&node) {
+            if let Some(old) = in_map.get(&node) {
+                let next = old.widen(&in_set);
+                widened |= next != in_set;
+                in_set = next;
+            }
+        }
+        let out_set = transfer(&in_set, &node);
+        in_map.insert(node, in_set);
+        if out_map.get(&node) != Some(&out_set) {
+            out_map.insert(node, out_set);
+            worklist.extend(
+                cfg.graph
+                    .neighbors_directed(node, graph_direction.opposite())
+                    .map(|s| index[&s]),
+            );
+        }
+    }
+
+    // Widening overshoots, a few sweeps without it win back precision while staying sound
+    for _ in 0..NARROWING_SWEEPS {
+        if !widened {
+            break;
+        }
+        widened = false;
+        for &node in &order {
+            let in_set = meet_preds(node, &out_map);
+            let out_set = transfer(&in_set, &node);
+            in_map.insert(node, in_set);
+            if out_map.get(&node) != Some(&out_set) {
+                out_map.insert(node, out_set);
+                widened = true;
+            }
         }
     }
     // Swap in and out when going backwards
@@ -65,3 +284,20 @@ pub fn run_worklist<T: Analysis>(cfg: &CFG) -> DataFlowResult<T> {
     }
     DataFlowResult { in_map, out_map }
 }
+
+/// Orders the nodes reachable from the entry in reverse postorder, followed by the others
+fn reverse_postorder(cfg: &CFG) -> Vec<CFGNode> {
+    let mut order: Vec<_> = DfsPostOrder::new(&cfg.graph, CFGNode::Block(0))
+        .iter(&cfg.graph)
+        .collect();
+    order.reverse();
+    let reached: HashSet<_> = order.iter().copied().collect();
+    let mut rest: Vec<_> = cfg
+        .graph
+        .nodes()
+        .filter(|node| !reached.contains(node))
+        .collect();
+    rest.sort();
+    order.extend(rest);
+    order
+}
@@ -1,9 +1,11 @@
 use std::{collections::HashSet, ops::Deref};
 
-use bbb::BlockHelpers;
+use crate::{Analysis, DataFlowDisplay, Direction};
+use bril_rs::Instruction;
 use cfg::{CFGNode, CFG};
-use df::{Analysis, DataFlowDisplay, Direction};
 use itertools::Itertools;
+use serde_json::{json, Value};
+use util::SafeAccess;
 
 #[derive(Clone, PartialEq, Eq, Debug, Default)]
 pub struct LiveVars(HashSet<String>);
@@ -18,24 +20,41 @@ impl Deref for LiveVars {
 
 impl Analysis for LiveVars {
     fn direction() -> Direction {
-        Direction::Forward
+        Direction::Backward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        LiveVars(HashSet::new())
+    }
+
+    fn bottom(cfg: &CFG) -> Self {
+        let vars = cfg
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instrs)
+            .flat_map(|instr| {
+                instr
+                    .get_args()
+                    .into_iter()
+                    .flatten()
+                    .chain(instr.get_dest())
+            })
+            .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+            .collect();
+        LiveVars(vars)
     }
 
     fn meet(&self, other: &Self) -> Self {
         LiveVars(self.union(&other).cloned().collect())
     }
 
-    fn transfer(&self, node: &CFGNode, cfg: &CFG) -> Self {
-        if let CFGNode::Block(block_index) = node {
-            let block = cfg.blocks.get(*block_index).unwrap();
-            let mut gen_set = block.get_uses();
-            let kill_set = block.get_defs();
-            let mut in_set: HashSet<_> = self.iter().cloned().collect();
-            in_set.retain(|variable| !kill_set.contains(variable));
-            gen_set.extend(in_set);
-            return LiveVars(gen_set);
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut live = self.0.clone();
+        if let Some(dest) = instr.get_dest() {
+            live.remove(&dest);
         }
-        self.clone()
+        live.extend(instr.get_args().unwrap_or_default());
+        LiveVars(live)
     }
 }
 
@@ -47,4 +66,8 @@ impl DataFlowDisplay for LiveVars {
 
         self.iter().sorted().cloned().collect::<Vec<_>>().join(" ")
     }
+
+    fn to_json(&self, _cfg: &CFG) -> Value {
+        json!(self.iter().sorted().collect::<Vec<_>>())
+    }
 }
@@ -1,53 +1,143 @@
 use bril_rs::load_program_from_read;
-use df::{run_worklist, DataFlowDisplay, DataFlowResult};
-use std::{env, io};
+use df::{
+    available_exprs::AvailableExprs,
+    definite_assignment::{uninitialized_uses, DefinitelyAssigned},
+    intervals::Intervals,
+    live_vars::LiveVars,
+    reaching_defs::ReachingDefs,
+    run_worklist, Analysis, DataFlowResult,
+};
+use serde_json::{json, Value};
+use std::{env, io, process};
 
-use cfg::{generate_cfg, CFGNode, CFG};
-
-use live_vars::LiveVars;
-use reaching_defs::ReachingDefs;
-
-mod live_vars;
-mod reaching_defs;
+use cfg::{generate_cfg, CFGNode, Dot, CFG};
 
 enum ResultWrapper {
     ReachingDefs(DataFlowResult<ReachingDefs>),
     LiveVars(DataFlowResult<LiveVars>),
+    AvailableExprs(DataFlowResult<AvailableExprs>),
+    Intervals(DataFlowResult<Intervals>),
+    DefinitelyAssigned(DataFlowResult<DefinitelyAssigned>),
 }
 
 impl ResultWrapper {
-    fn print_cfg(&self, cfg: &CFG) {
+    fn print_cfg(&self, cfg: &CFG, instrs: bool) {
         let dyn_print: Box<dyn Fn(usize)> = match self {
             ResultWrapper::ReachingDefs(res) => Box::new(|i| {
-                print_node(CFGNode::Block(i), &cfg, res);
+                print_node(CFGNode::Block(i), cfg, res, instrs);
             }),
             ResultWrapper::LiveVars(res) => Box::new(|i| {
-                print_node(CFGNode::Block(i), &cfg, res);
+                print_node(CFGNode::Block(i), cfg, res, instrs);
+            }),
+            ResultWrapper::AvailableExprs(res) => Box::new(|i| {
+                print_node(CFGNode::Block(i), cfg, res, instrs);
+            }),
+            ResultWrapper::Intervals(res) => Box::new(|i| {
+                print_node(CFGNode::Block(i), cfg, res, instrs);
+            }),
+            ResultWrapper::DefinitelyAssigned(res) => Box::new(|i| {
+                print_node(CFGNode::Block(i), cfg, res, instrs);
             }),
         };
         for i in 0..cfg.blocks.len() {
             dyn_print(i);
         }
     }
+
+    fn to_json(&self, cfg: &CFG) -> Value {
+        match self {
+            ResultWrapper::ReachingDefs(res) => res.to_json(cfg),
+            ResultWrapper::LiveVars(res) => res.to_json(cfg),
+            ResultWrapper::AvailableExprs(res) => res.to_json(cfg),
+            ResultWrapper::Intervals(res) => res.to_json(cfg),
+            ResultWrapper::DefinitelyAssigned(res) => res.to_json(cfg),
+        }
+    }
+
+    fn add_to_dot(&self, cfg: &CFG, dot: &mut Dot) {
+        match self {
+            ResultWrapper::ReachingDefs(res) => res.add_to_dot(cfg, dot),
+            ResultWrapper::LiveVars(res) => res.add_to_dot(cfg, dot),
+            ResultWrapper::AvailableExprs(res) => res.add_to_dot(cfg, dot),
+            ResultWrapper::Intervals(res) => res.add_to_dot(cfg, dot),
+            ResultWrapper::DefinitelyAssigned(res) => res.add_to_dot(cfg, dot),
+        }
+    }
 }
 
-fn print_node<T: DataFlowDisplay>(node: CFGNode, cfg: &CFG, res: &DataFlowResult<T>) {
-    let format = |f: Option<&T>| f.map_or("N/A".to_string(), |set| set.generate_string(&cfg));
+fn print_node<T: Analysis>(node: CFGNode, cfg: &CFG, res: &DataFlowResult<T>, instrs: bool) {
+    let format = |f: Option<&T>| f.map_or("N/A".to_string(), |set| set.generate_string(cfg));
     let label = match node {
         CFGNode::Block(i) => &cfg.blocks.get(i).unwrap().label,
         CFGNode::Return => "return",
     };
     println!("[{}]", label);
     println!("   in: {}", format(res.in_map.get(&node)));
+    if instrs {
+        // Shows the fact holding after each instruction
+        let block = cfg.get_block(node).unwrap();
+        for (instr, (_, after)) in block.instrs.iter().zip(res.instr_facts(node, cfg)) {
+            println!("     {}", instr);
+            println!("       {}", after.generate_string(cfg));
+        }
+    }
     println!("   out: {}", format(res.out_map.get(&node)));
 }
 
 fn run_reaching_defs(cfg: &CFG) -> ResultWrapper {
-    ResultWrapper::ReachingDefs(run_worklist::<ReachingDefs>(&cfg))
+    ResultWrapper::ReachingDefs(run_worklist::<ReachingDefs>(cfg))
 }
 
 fn run_live_vars(cfg: &CFG) -> ResultWrapper {
-    ResultWrapper::LiveVars(run_worklist::<LiveVars>(&cfg))
+    ResultWrapper::LiveVars(run_worklist::<LiveVars>(cfg))
+}
+
+fn run_available_exprs(cfg: &CFG) -> ResultWrapper {
+    ResultWrapper::AvailableExprs(run_worklist::<AvailableExprs>(cfg))
+}
+
+fn run_intervals(cfg: &CFG) -> ResultWrapper {
+    ResultWrapper::Intervals(run_worklist::<Intervals>(cfg))
+}
+
+fn run_definite_assignment(cfg: &CFG) -> ResultWrapper {
+    ResultWrapper::DefinitelyAssigned(run_worklist::<DefinitelyAssigned>(cfg))
+}
+
+/// Returns whether --format asks for json, exiting on formats other than text and json
+fn json_format(args: &[String]) -> bool {
+    let Some(i) = args.iter().position(|arg| arg == "--format") else {
+        return false;
+    };
+    match args.get(i + 1).map(String::as_str) {
+        Some("json") => true,
+        Some("text") => false,
+        format => {
+            eprintln!("Unknown format: {}", format.unwrap_or_default());
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints every possibly unassigned read of the program, failing if there is one
+fn check_uninitialized() -> ! {
+    let json_output = json_format(&env::args().collect::<Vec<_>>());
+    let program = load_program_from_read(io::stdin());
+    let mut uses = Vec::new();
+    for function in program.functions {
+        let cfg = generate_cfg(&function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        uses.extend(uninitialized_uses(&cfg));
+    }
+    if json_output {
+        let uses: Vec<_> = uses.iter().map(|use_| use_.to_json()).collect();
+        println!("{}", serde_json::to_string_pretty(&uses).unwrap());
+    } else {
+        uses.iter().for_each(|use_| println!("{}", use_));
+    }
+    process::exit(if uses.is_empty() { 0 } else { 1 });
 }
 
 fn main() -> io::Result<()> {
@@ -56,17 +146,45 @@ fn main() -> io::Result<()> {
     let run_algorithm = match alg_input {
         "reaching_defs" => run_reaching_defs,
         "live_vars" => run_live_vars,
+        "available_exprs" => run_available_exprs,
+        "intervals" => run_intervals,
+        "definite_assignment" => run_definite_assignment,
+        "uninitialized" => check_uninitialized(),
         _ => {
             eprintln!("Unknown command: {}", alg_input);
-            std::process::exit(1);
+            process::exit(1);
         }
     };
 
+    let dot_output = args.iter().any(|arg| arg == "--dot");
+    let instr_output = args.iter().any(|arg| arg == "--instrs");
+    let json_output = json_format(&args);
+
     let program = load_program_from_read(io::stdin());
+    let mut dot = Dot::new();
+    let mut functions = Vec::new();
     for function in program.functions {
-        let cfg = generate_cfg(&function);
-        run_algorithm(&cfg).print_cfg(&cfg);
+        let cfg = generate_cfg(&function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        if dot_output {
+            run_algorithm(&cfg).add_to_dot(&cfg, &mut dot);
+            continue;
+        }
+        if json_output {
+            functions.push(run_algorithm(&cfg).to_json(&cfg));
+            continue;
+        }
+        run_algorithm(&cfg).print_cfg(&cfg, instr_output);
         // print_node(CFGNode::Return, &df_res, &cfg);
     }
+    if dot_output {
+        print!("{}", dot);
+    }
+    if json_output {
+        let output = json!({ "analysis": alg_input, "functions": functions });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    }
     Ok(())
 }
@@ -1,12 +1,14 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     ops::Deref,
 };
 
-use bbb::BlockHelpers;
+use crate::{Analysis, DataFlowDisplay, Direction};
+use bril_rs::Instruction;
 use cfg::{CFGNode, CFG};
-use df::{Analysis, DataFlowDisplay, Direction};
 use itertools::Itertools;
+use serde_json::{json, Value};
+use util::SafeAccess;
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Definition {
@@ -30,30 +32,45 @@ impl Analysis for ReachingDefs {
         Direction::Forward
     }
 
+    fn top(_cfg: &CFG) -> Self {
+        ReachingDefs(HashSet::new())
+    }
+
+    fn bottom(cfg: &CFG) -> Self {
+        let defs = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(block_index, block)| {
+                block.instrs.iter().flat_map(move |instr| {
+                    instr.get_dest().map(|variable| Definition {
+                        variable,
+                        block_index,
+                    })
+                })
+            })
+            .collect();
+        ReachingDefs(defs)
+    }
+
     fn meet(&self, other: &Self) -> Self {
         ReachingDefs(self.union(&other).cloned().collect())
     }
 
-    fn transfer(&self, node: &CFGNode, cfg: &CFG) -> Self {
-        if let CFGNode::Block(block_index) = node {
-            let block = cfg.blocks.get(*block_index).unwrap();
-            let new_defs = block.get_defs();
-            let mut out_set: HashSet<_> = new_defs
-                .iter()
-                .cloned()
-                .map(|variable| Definition {
-                    block_index: *block_index,
-                    variable,
-                })
-                .collect();
-            let kill_set = self
-                .iter()
-                .cloned()
-                .filter(|def| !new_defs.contains(&def.variable));
-            out_set.extend(kill_set);
-            return ReachingDefs(out_set);
-        }
-        self.clone()
+    fn transfer_instr(&self, instr: &Instruction, node: &CFGNode) -> Self {
+        let (Some(variable), CFGNode::Block(block_index)) = (instr.get_dest(), node) else {
+            return self.clone();
+        };
+        let mut defs: HashSet<_> = self
+            .iter()
+            .filter(|def| def.variable != variable)
+            .cloned()
+            .collect();
+        defs.insert(Definition {
+            variable,
+            block_index: *block_index,
+        });
+        ReachingDefs(defs)
     }
 }
 
@@ -88,4 +105,26 @@ impl DataFlowDisplay for ReachingDefs {
             .map(|str| "\n      ".to_string() + &str)
             .collect()
     }
+
+    /// Maps every variable to the labels of the blocks whose definitions reach
+    fn to_json(&self, cfg: &CFG) -> Value {
+        let mut var_map: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for def in self.iter() {
+            var_map
+                .entry(&def.variable)
+                .or_default()
+                .push(def.block_index);
+        }
+        let labels = |blocks: &Vec<usize>| -> Vec<String> {
+            blocks
+                .iter()
+                .sorted()
+                .map(|&b| cfg.blocks[b].label.clone())
+                .collect()
+        };
+        json!(var_map
+            .iter()
+            .map(|(var, blocks)| (*var, labels(blocks)))
+            .collect::<BTreeMap<_, _>>())
+    }
 }
@@ -1,10 +1,16 @@
 use std::collections::{HashMap, HashSet};
 
-use cfg::{CFGNode, CFG};
+use cfg::{CFGNode, Edge, CFG};
+use itertools::Itertools;
+#[cfg(feature = "verify")]
+use petgraph::{algo::all_simple_paths, visit::NodeCount};
 use petgraph::{
-    algo::all_simple_paths,
+    graphmap::NodeTrait,
     prelude::DiGraphMap,
-    visit::{DfsPostOrder, IntoNeighbors, Visitable},
+    visit::{
+        DfsPostOrder, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, Reversed,
+        Visitable,
+    },
     Direction::Incoming,
 };
 
@@ -19,147 +25,311 @@ pub struct DomResult<T> {
     pub immediate_dominator: HashMap<T, T>,
     /// dominator_tree contain nodes whose parent-child relationship is immediate dominance
     pub dominator_tree: DiGraphMap<T, ()>,
+    /// tree answers dominance queries in constant time
+    pub tree: DomTree<T>,
 }
 
-fn reverse_postorder<G, T>(graph: G, start: T) -> Vec<T>
+impl<T: NodeTrait> DomResult<T> {
+    /// Returns the iterated dominance frontier of defs, the nodes where values defined in defs
+    /// meet and so need a phi
+    pub fn iterated_frontier(&self, defs: impl IntoIterator<Item = T>) -> HashSet<T> {
+        let mut result = HashSet::new();
+        let mut worklist: Vec<_> = defs.into_iter().collect();
+        while let Some(node) = worklist.pop() {
+            for &frontier in self.dominance_frontier.get(&node).into_iter().flatten() {
+                if result.insert(frontier) {
+                    worklist.push(frontier);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Dominator tree numbered by a depth first walk, so a node dominates exactly the nodes whose
+/// preorder and postorder numbers fall within its own
+pub struct DomTree<T> {
+    root: T,
+    idom: HashMap<T, T>,
+    /// children[x] is the nodes x immediately dominates, sorted
+    children: HashMap<T, Vec<T>>,
+    /// numbers[x] is the preorder number, postorder number and depth of x
+    numbers: HashMap<T, (usize, usize, usize)>,
+}
+
+impl<T: NodeTrait> DomTree<T> {
+    pub fn new(root: T, immediate_dominator: &HashMap<T, T>) -> Self {
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+        for (&node, &idom) in immediate_dominator {
+            children.entry(idom).or_default().push(node);
+        }
+        for nodes in children.values_mut() {
+            nodes.sort();
+        }
+
+        let mut numbers = HashMap::new();
+        let (mut pre, mut post) = (0, 0);
+        let mut stack = vec![(root, 0, 0)];
+        while let Some((node, depth, next_child)) = stack.pop() {
+            if next_child == 0 {
+                numbers.insert(node, (pre, 0, depth));
+                pre += 1;
+            }
+            match children.get(&node).and_then(|nodes| nodes.get(next_child)) {
+                Some(&child) => {
+                    stack.push((node, depth, next_child + 1));
+                    stack.push((child, depth + 1, 0));
+                }
+                None => {
+                    numbers.get_mut(&node).unwrap().1 = post;
+                    post += 1;
+                }
+            }
+        }
+
+        DomTree {
+            root,
+            idom: immediate_dominator.clone(),
+            children,
+            numbers,
+        }
+    }
+
+    pub fn root(&self) -> T {
+        self.root
+    }
+
+    /// Whether node is reachable from the root, and so in the tree
+    pub fn contains(&self, node: T) -> bool {
+        self.numbers.contains_key(&node)
+    }
+
+    pub fn idom(&self, node: T) -> Option<T> {
+        self.idom.get(&node).copied()
+    }
+
+    /// Returns the nodes node immediately dominates, in sorted order
+    pub fn children(&self, node: T) -> impl Iterator<Item = T> + '_ {
+        self.children.get(&node).into_iter().flatten().copied()
+    }
+
+    /// Returns the number of strict dominators of node
+    pub fn depth(&self, node: T) -> Option<usize> {
+        self.numbers.get(&node).map(|&(_, _, depth)| depth)
+    }
+
+    /// Whether every path from the root to b goes through a. False when either is unreachable
+    pub fn dominates(&self, a: T, b: T) -> bool {
+        match (self.numbers.get(&a), self.numbers.get(&b)) {
+            (Some(&(a_pre, a_post, _)), Some(&(b_pre, b_post, _))) => {
+                a_pre <= b_pre && b_post <= a_post
+            }
+            _ => false,
+        }
+    }
+
+    pub fn strictly_dominates(&self, a: T, b: T) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    /// Returns the closest node dominating both a and b
+    pub fn lca(&self, mut a: T, mut b: T) -> Option<T> {
+        let (mut a_depth, mut b_depth) = (self.depth(a)?, self.depth(b)?);
+        while a_depth > b_depth {
+            a = self.idom[&a];
+            a_depth -= 1;
+        }
+        while b_depth > a_depth {
+            b = self.idom[&b];
+            b_depth -= 1;
+        }
+        while a != b {
+            a = self.idom[&a];
+            b = self.idom[&b];
+        }
+        Some(a)
+    }
+
+    /// Returns the nodes of the tree with every node before the nodes it dominates
+    pub fn preorder(&self) -> Vec<T> {
+        self.numbers
+            .iter()
+            .sorted_by_key(|(_, &(pre, _, _))| pre)
+            .map(|(&node, _)| node)
+            .collect()
+    }
+}
+
+fn reverse_postorder<G>(graph: G, root: G::NodeId) -> Vec<G::NodeId>
 where
-    G: IntoNeighbors + Visitable<NodeId = T>,
-    T: Copy + PartialEq,
+    G: IntoNeighbors + Visitable,
 {
-    let mut dfs = DfsPostOrder::new(&graph, start);
+    let mut dfs = DfsPostOrder::new(graph, root);
     let mut result = Vec::new();
-    while let Some(next) = dfs.next(&graph) {
+    while let Some(next) = dfs.next(graph) {
         result.push(next);
     }
     result.reverse();
     result
 }
 
-pub trait DominatorUtil {
-    fn find_dominators(&self) -> DomResult<CFGNode>;
-}
-
-impl DominatorUtil for CFG {
-    fn find_dominators(&self) -> DomResult<CFGNode> {
-        let all_nodes: HashSet<_> = self.graph.nodes().into_iter().collect();
+/// Finds the dominators of every node of graph reachable from root with the algorithm of
+/// Cooper, Harvey and Kennedy. Unreachable nodes have no dominators, dominate nothing and
+/// have an empty frontier
+pub fn dominators<G>(graph: G, root: G::NodeId) -> DomResult<G::NodeId>
+where
+    G: IntoNeighborsDirected + IntoNodeIdentifiers + Visitable,
+    G::NodeId: NodeTrait,
+{
+    let rpo = reverse_postorder(graph, root);
+    let order: HashMap<_, _> = rpo.iter().enumerate().map(|(i, &n)| (n, i)).collect();
 
-        let mut dominators: HashMap<_, _> = all_nodes
-            .iter()
-            .map(|node| (*node, all_nodes.clone()))
-            .collect();
-
-        dominators.insert(
-            CFGNode::Block(0),
-            vec![CFGNode::Block(0)].into_iter().collect(),
-        );
-
-        // Navigate in reverse postorder to terminate faster
-        let reverse_postorder_nodes = reverse_postorder(&self.graph, CFGNode::Block(0));
-
-        loop {
-            let mut changed = false;
-            for &node in reverse_postorder_nodes.iter().skip(1) {
-                let prev_doms = dominators.get(&node).cloned();
-                let mut new_doms = self
-                    .graph
-                    .neighbors_directed(node, Incoming)
-                    .map(|n| dominators.get(&n).unwrap())
-                    .cloned()
-                    .reduce(|a, b| a.intersection(&b).copied().collect::<HashSet<_>>())
-                    .unwrap();
-                new_doms.insert(node);
-                let insert_res = dominators.insert(node, new_doms);
-                if insert_res != prev_doms {
-                    changed = true;
-                }
+    // Walks up from both nodes until they meet, using that a dominator always comes
+    // earlier in reverse postorder
+    let intersect = |idom: &HashMap<G::NodeId, G::NodeId>, mut a, mut b| {
+        while a != b {
+            while order[&a] > order[&b] {
+                a = idom[&a];
             }
-            if !changed {
-                break;
+            while order[&b] > order[&a] {
+                b = idom[&b];
             }
         }
+        a
+    };
 
-        let mut dominated_by: HashMap<_, HashSet<_>> = HashMap::new();
-        for (&node, doms) in &dominators {
-            for &dom in doms {
-                dominated_by
-                    .entry(dom)
-                    .or_insert_with(HashSet::new)
-                    .insert(node);
+    let mut idom = HashMap::from([(root, root)]);
+    loop {
+        let mut changed = false;
+        for &node in rpo.iter().skip(1) {
+            let new_idom = graph
+                .neighbors_directed(node, Incoming)
+                .filter(|pred| idom.contains_key(pred))
+                .reduce(|a, b| intersect(&idom, a, b))
+                .expect("a node after the root in reverse postorder has a visited predecessor");
+            if idom.insert(node, new_idom) != Some(new_idom) {
+                changed = true;
             }
         }
+        if !changed {
+            break;
+        }
+    }
+    idom.remove(&root);
 
-        let find_frontier = |node: &CFGNode| {
-            let subs = dominated_by
-                .get(node)
-                .expect("all nodes should exist as keys in dominated_by");
-            let strict_subs = subs.difference(&HashSet::from([*node])).copied().collect();
-            subs.iter()
-                .flat_map(|dom| self.graph.neighbors(*dom))
-                .collect::<HashSet<_>>()
-                .difference(&strict_subs)
-                .copied()
-                .collect()
-        };
-
-        let dominance_frontier = all_nodes
-            .iter()
-            .copied()
-            .map(|node| (node, find_frontier(&node)))
-            .collect();
-
-        let find_immediate_dom = |node: &CFGNode| {
-            let doms = dominators
-                .get(node)
-                .expect("all nodes should exist as keys in dominators");
-            doms.iter().filter(|&d| d != node).find(|&d| {
-                let candidate_doms = dominated_by
-                    .get(d)
-                    .expect("all nodes should exist as keys in dominators");
-                let intersection: HashSet<_> = candidate_doms.intersection(doms).collect();
-                // The immediate dominator will only have two things in this intersection: the dominator and the dominated node
-                intersection == HashSet::from([node, d])
-            })
-        };
-
-        let mut immediate_dominator = HashMap::new();
-        for &node in &all_nodes {
-            if let Some(&dom) = find_immediate_dom(&node) {
-                immediate_dominator.insert(node, dom);
-            }
+    let mut dominators = HashMap::new();
+    let mut dominated_by: HashMap<_, HashSet<_>> = graph
+        .node_identifiers()
+        .map(|node| (node, HashSet::new()))
+        .collect();
+    for &node in &rpo {
+        let mut doms = HashSet::from([node]);
+        let mut runner = node;
+        while let Some(&parent) = idom.get(&runner) {
+            doms.insert(parent);
+            runner = parent;
+        }
+        for &dom in &doms {
+            dominated_by.entry(dom).or_default().insert(node);
         }
+        dominators.insert(node, doms);
+    }
 
-        let mut dominator_tree = DiGraphMap::new();
-        dominator_tree.add_node(CFGNode::Block(0));
-        for (&node, &imm_dom) in &immediate_dominator {
-            dominator_tree.add_edge(imm_dom, node, ());
+    // Every node from a predecessor of a join up to, but not including, the join's idom
+    // has the join in its frontier
+    let mut dominance_frontier: HashMap<_, HashSet<_>> = graph
+        .node_identifiers()
+        .map(|node| (node, HashSet::new()))
+        .collect();
+    for &node in &rpo {
+        for pred in graph.neighbors_directed(node, Incoming) {
+            if !order.contains_key(&pred) {
+                continue;
+            }
+            let mut runner = Some(pred);
+            while let Some(r) = runner.filter(|&r| idom.get(&node) != Some(&r)) {
+                dominance_frontier.entry(r).or_default().insert(node);
+                runner = idom.get(&r).copied();
+            }
         }
+    }
+
+    let mut dominator_tree = DiGraphMap::new();
+    dominator_tree.add_node(root);
+    for &node in rpo.iter().skip(1) {
+        dominator_tree.add_edge(idom[&node], node, ());
+    }
+
+    DomResult {
+        dominators,
+        dominated_by,
+        dominance_frontier,
+        tree: DomTree::new(root, &idom),
+        immediate_dominator: idom,
+        dominator_tree,
+    }
+}
 
-        for node in all_nodes {
-            verify_dominators(&node, dominators.get(&node).unwrap(), &self);
+pub trait DominatorUtil {
+    fn find_dominators(&self) -> DomResult<CFGNode>;
+    /// Dominators of the reversed cfg from its return node, so a node post-dominates every
+    /// node that must pass through it to return. Nodes that never return have none
+    fn find_post_dominators(&self) -> DomResult<CFGNode>;
+    /// Graph with an edge from every branching node to each node control dependent on it,
+    /// labelled with the edge of the branch that decides whether the node runs
+    fn control_dependence(&self) -> DiGraphMap<CFGNode, Edge>;
+}
+
+impl DominatorUtil for CFG {
+    fn find_dominators(&self) -> DomResult<CFGNode> {
+        let result = dominators(&self.graph, CFGNode::Block(0));
+        #[cfg(feature = "verify")]
+        for (&node, doms) in &result.dominators {
+            verify_dominators(&self.graph, CFGNode::Block(0), node, doms);
         }
+        result
+    }
 
-        DomResult {
-            dominators,
-            dominated_by,
-            dominance_frontier,
-            immediate_dominator,
-            dominator_tree,
+    fn find_post_dominators(&self) -> DomResult<CFGNode> {
+        dominators(Reversed(&self.graph), CFGNode::Return)
+    }
+
+    fn control_dependence(&self) -> DiGraphMap<CFGNode, Edge> {
+        let post = self.find_post_dominators();
+        let ipdom = &post.immediate_dominator;
+        let mut graph = DiGraphMap::new();
+        for node in self.graph.nodes() {
+            graph.add_node(node);
+        }
+        // Taking an edge decides that every node from its target up to, but not including,
+        // the source's immediate post-dominator runs
+        for (from, to, edge) in self.graph.all_edges() {
+            let mut runner = Some(to);
+            while let Some(r) = runner.filter(|&r| ipdom.get(&from) != Some(&r)) {
+                graph.add_edge(from, r, edge.clone());
+                runner = ipdom.get(&r).copied();
+            }
         }
+        graph
     }
 }
 
-/// Panics if input dominators does not match actual dominators of input node
-fn verify_dominators(node: &CFGNode, dominators: &HashSet<CFGNode>, cfg: &CFG) {
-    let naive_doms_opt = match node {
-        CFGNode::Block(0) => Some(HashSet::from([CFGNode::Block(0)])),
-        _ => all_simple_paths::<HashSet<_>, _>(&cfg.graph, CFGNode::Block(0), *node, 0, None)
-            .reduce(|a, b| a.intersection(&b).copied().collect()),
+/// Panics if dominators does not match the intersection of every simple path from root to
+/// node. Exponential in the size of the graph, so only checked with the verify feature
+#[cfg(feature = "verify")]
+fn verify_dominators<G>(graph: G, root: G::NodeId, node: G::NodeId, dominators: &HashSet<G::NodeId>)
+where
+    G: IntoNeighborsDirected + Visitable + NodeCount,
+    G::NodeId: NodeTrait + std::fmt::Debug,
+{
+    let naive_doms = if node == root {
+        HashSet::from([root])
+    } else {
+        all_simple_paths::<HashSet<_>, _>(graph, root, node, 0, None)
+            .reduce(|a, b| a.intersection(&b).copied().collect())
+            .expect("node is reachable from root")
     };
-
-    // None means node is unreachable from entry
-    if let Some(naive_doms) = naive_doms_opt {
-        if *dominators != naive_doms {
-            panic!("Efficient algorithm calculates {:?}'s dominators is {:?}, but naive algorithm thinks dominators is {:?}", node, dominators, naive_doms)
-        }
+    if *dominators != naive_doms {
+        panic!("Efficient algorithm calculates {:?}'s dominators is {:?}, but naive algorithm thinks dominators is {:?}", node, dominators, naive_doms)
     }
 }
@@ -1,10 +1,12 @@
 use bril_rs::load_program;
-use cfg::{generate_cfg, CFGNode, CFG};
+use cfg::{generate_cfg, CFGNode, Dot, Edge, CFG};
 use dom::{DomResult, DominatorUtil};
 use itertools::Itertools;
+use petgraph::{prelude::DiGraphMap, Direction::Incoming};
+use serde_json::{json, Value};
 use std::{
     collections::{HashMap, HashSet},
-    io,
+    env, io, process,
 };
 
 trait PrettyPrint {
@@ -52,28 +54,44 @@ fn print_results(results: &HashMap<CFGNode, impl PrettyPrint>, cfg: &CFG) {
     }
 }
 
-fn print_dominators(cfg: &CFG) {
+fn print_dominators(cfg: &CFG, result: DomResult<CFGNode>, post: bool) {
     let DomResult {
         dominators,
         dominated_by: _,
         dominance_frontier,
         immediate_dominator,
         dominator_tree,
-    } = cfg.find_dominators();
+        ..
+    } = result;
+    let headings = if post {
+        [
+            "Post-dominators",
+            "Post-domination Frontier",
+            "Immediate Post-dominator",
+            "Post-dominator Tree",
+        ]
+    } else {
+        [
+            "Dominators",
+            "Domination Frontier",
+            "Immediate Dominator",
+            "Dominator Tree",
+        ]
+    };
 
-    println!("Dominators");
+    println!("{}", headings[0]);
     print_results(&dominators, &cfg);
     println!("");
 
-    println!("Domination Frontier");
+    println!("{}", headings[1]);
     print_results(&dominance_frontier, &cfg);
     println!("");
 
-    println!("Immediate Dominator");
+    println!("{}", headings[2]);
     print_results(&immediate_dominator, &cfg);
     println!("");
 
-    println!("Dominator Tree");
+    println!("{}", headings[3]);
     let adj_list: HashMap<_, HashSet<_>> = dominator_tree
         .nodes()
         .map(|n| (n, dominator_tree.neighbors(n).collect()))
@@ -81,11 +99,161 @@ fn print_dominators(cfg: &CFG) {
     print_results(&adj_list, &cfg);
 }
 
+/// Returns the branches node is control dependent on with the edge deciding it, sorted
+fn dependences(cdg: &DiGraphMap<CFGNode, Edge>, node: CFGNode) -> Vec<(CFGNode, Option<bool>)> {
+    cdg.edges_directed(node, Incoming)
+        .map(|(branch, _, edge)| {
+            let taken = match edge {
+                Edge::Always => None,
+                Edge::Bool(b) => Some(*b),
+            };
+            (branch, taken)
+        })
+        .sorted()
+        .collect()
+}
+
+fn print_control_dependence(cfg: &CFG) {
+    println!("Control Dependence");
+    let cdg = cfg.control_dependence();
+    for i in 0..cfg.blocks.len() {
+        let node = CFGNode::Block(i);
+        let branches = dependences(&cdg, node)
+            .into_iter()
+            .map(|(branch, taken)| match taken {
+                Some(b) => format!("{} ({})", branch.pretty_print(cfg), b),
+                None => branch.pretty_print(cfg),
+            })
+            .join(", ");
+        println!("[{}]: {{ {} }}", node.pretty_print(cfg), branches);
+    }
+}
+
+fn control_dependence_json(cfg: &CFG) -> Value {
+    let cdg = cfg.control_dependence();
+    let blocks: Vec<_> = (0..cfg.blocks.len())
+        .map(CFGNode::Block)
+        .map(|node| {
+            let depends_on: Vec<_> = dependences(&cdg, node)
+                .into_iter()
+                .map(|(branch, taken)| json!({ "branch": branch.pretty_print(cfg), "edge": taken }))
+                .collect();
+            json!({ "label": node.pretty_print(cfg), "depends_on": depends_on })
+        })
+        .collect();
+    json!({ "function": cfg.name, "blocks": blocks })
+}
+
+fn node_list(nodes: Option<&HashSet<CFGNode>>, cfg: &CFG) -> Vec<String> {
+    nodes
+        .into_iter()
+        .flatten()
+        .sorted()
+        .map(|node| node.pretty_print(cfg))
+        .collect()
+}
+
+fn dominators_json(cfg: &CFG, result: DomResult<CFGNode>) -> Value {
+    let DomResult {
+        dominators,
+        dominated_by: _,
+        dominance_frontier,
+        immediate_dominator,
+        dominator_tree,
+        ..
+    } = result;
+
+    let blocks: Vec<_> = (0..cfg.blocks.len())
+        .map(CFGNode::Block)
+        .map(|node| {
+            let children: HashSet<_> = dominator_tree.neighbors(node).collect();
+            json!({
+                "label": node.pretty_print(cfg),
+                "dominators": node_list(dominators.get(&node), cfg),
+                "frontier": node_list(dominance_frontier.get(&node), cfg),
+                "idom": immediate_dominator.get(&node).map(|idom| idom.pretty_print(cfg)),
+                "children": node_list(Some(&children), cfg),
+            })
+        })
+        .collect();
+    let tree_edges: Vec<_> = dominator_tree
+        .all_edges()
+        .map(|(parent, child, _)| (parent, child))
+        .sorted()
+        .map(|(parent, child)| [parent.pretty_print(cfg), child.pretty_print(cfg)])
+        .collect();
+    json!({ "function": cfg.name, "blocks": blocks, "tree_edges": tree_edges })
+}
+
+/// Returns whether --format asks for json, exiting on formats other than text and json
+fn json_format() -> bool {
+    let args: Vec<_> = env::args().collect();
+    let Some(i) = args.iter().position(|arg| arg == "--format") else {
+        return false;
+    };
+    match args.get(i + 1).map(String::as_str) {
+        Some("json") => true,
+        Some("text") => false,
+        format => {
+            eprintln!("Unknown format: {}", format.unwrap_or_default());
+            process::exit(1);
+        }
+    }
+}
+
 fn main() -> io::Result<()> {
+    let dot_output = env::args().any(|arg| arg == "--dot");
+    let post = env::args().any(|arg| arg == "--post");
+    let cdg = env::args().any(|arg| arg == "--cdg");
+    let json_output = json_format();
+
     let program = load_program();
+    let mut dot = Dot::new();
+    let mut functions = Vec::new();
     for function in program.functions {
-        let cfg = generate_cfg(&function);
-        print_dominators(&cfg);
+        let cfg = generate_cfg(&function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        let result = || {
+            if post {
+                cfg.find_post_dominators()
+            } else {
+                cfg.find_dominators()
+            }
+        };
+        if dot_output {
+            dot.add_cfg(&cfg, |_| Vec::new());
+            if cdg {
+                let title = format!("{} control dependence", cfg.name);
+                dot.add_node_graph(&title, &cfg, &cfg.control_dependence());
+            } else {
+                let tree = if post { "post-dominator" } else { "dominator" };
+                let title = format!("{} {} tree", cfg.name, tree);
+                dot.add_node_graph(&title, &cfg, &result().dominator_tree);
+            }
+            continue;
+        }
+        if json_output {
+            functions.push(if cdg {
+                control_dependence_json(&cfg)
+            } else {
+                dominators_json(&cfg, result())
+            });
+            continue;
+        }
+        if cdg {
+            print_control_dependence(&cfg);
+        } else {
+            print_dominators(&cfg, result(), post);
+        }
+    }
+    if dot_output {
+        print!("{}", dot);
+    }
+    if json_output {
+        let output = json!({ "functions": functions });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
     }
     Ok(())
 }
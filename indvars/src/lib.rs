@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use bril_rs::{Instruction, Literal, ValueOps};
+use cfg::{CFGNode, CFG};
+use itertools::Itertools;
+use loops::Loop;
+use util::SafeAccess;
+
+pub use reduce::strength_reduce;
+
+mod reduce;
+
+/// A loop invariant operand, constants are kept by value so they can be materialized anywhere
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Var(String),
+    Const(i64),
+}
+
+/// i = phi(init, i + step), updated once per iteration
+#[derive(Clone, Debug)]
+pub struct BasicIv {
+    /// var is the phi in the loop header
+    pub var: String,
+    /// init is the value entering the loop
+    pub init: String,
+    pub step: Operand,
+    /// next is the updated value flowing back to the header
+    pub next: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DerivedKind {
+    /// basic * factor
+    Mul(Operand),
+    /// basic + offset
+    Add(Operand),
+    /// ptradd base basic
+    PtrAdd(String),
+}
+
+/// A value computed from a basic induction variable of the same loop
+#[derive(Clone, Debug)]
+pub struct DerivedIv {
+    pub var: String,
+    pub basic: String,
+    pub kind: DerivedKind,
+}
+
+#[derive(Default)]
+pub struct InductionVars {
+    pub basic: Vec<BasicIv>,
+    pub derived: Vec<DerivedIv>,
+}
+
+/// Variables defined by exactly one instruction, mapped to that instruction.
+/// Only these are considered, so code that is not in ssa form is left alone
+pub(crate) fn single_defs(cfg: &CFG) -> HashMap<String, (usize, usize)> {
+    cfg.blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(b, block)| {
+            block
+                .instrs
+                .iter()
+                .enumerate()
+                .filter_map(move |(i, instr)| instr.get_dest().map(|dest| (dest, (b, i))))
+        })
+        .into_group_map()
+        .into_iter()
+        .filter(|(_, sites)| sites.len() == 1)
+        .map(|(dest, sites)| (dest, sites[0]))
+        .collect()
+}
+
+struct Context<'a> {
+    cfg: &'a CFG,
+    l: &'a Loop,
+    defs: HashMap<String, (usize, usize)>,
+}
+
+impl Context<'_> {
+    fn def(&self, var: &str) -> Option<&Instruction> {
+        self.defs
+            .get(var)
+            .map(|&(b, i)| &self.cfg.blocks[b].instrs[i])
+    }
+
+    /// Returns var as an operand if its value is the same in every iteration of the loop
+    fn invariant(&self, var: &str) -> Option<Operand> {
+        if self.cfg.args.iter().any(|arg| arg.name == var) {
+            return Some(Operand::Var(var.to_string()));
+        }
+        match self.def(var)? {
+            Instruction::Constant {
+                value: Literal::Int(value),
+                ..
+            } => Some(Operand::Const(*value)),
+            _ => {
+                let (b, _) = self.defs[var];
+                (!self.l.body.contains(&CFGNode::Block(b))).then(|| Operand::Var(var.to_string()))
+            }
+        }
+    }
+
+    /// Matches an add of var and an invariant, in either order
+    fn add_of(&self, instr: &Instruction, var: &str) -> Option<Operand> {
+        match instr {
+            Instruction::Value {
+                op: ValueOps::Add,
+                args,
+                ..
+            } if args.len() == 2 => match (args[0] == var, args[1] == var) {
+                (true, false) => self.invariant(&args[1]),
+                (false, true) => self.invariant(&args[0]),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn basic_iv(&self, phi: &Instruction) -> Option<BasicIv> {
+        let Instruction::Value {
+            op: ValueOps::Phi,
+            dest,
+            args,
+            labels,
+            ..
+        } = phi
+        else {
+            return None;
+        };
+        let label_index: HashMap<_, _> = self
+            .cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block.label.as_str(), CFGNode::Block(i)))
+            .collect();
+        let (inside, outside): (Vec<_>, Vec<_>) =
+            args.iter().zip(labels).partition(|(_, label)| {
+                label_index
+                    .get(label.as_str())
+                    .is_some_and(|node| self.l.body.contains(node))
+            });
+        let init = outside.iter().map(|(arg, _)| *arg).all_equal_value().ok()?;
+        let next = inside.iter().map(|(arg, _)| *arg).all_equal_value().ok()?;
+        if init == "undefined" || !self.defs.contains_key(dest) {
+            return None;
+        }
+        let step = self.add_of(self.def(next)?, dest)?;
+        Some(BasicIv {
+            var: dest.clone(),
+            init: init.clone(),
+            step,
+            next: next.clone(),
+        })
+    }
+
+    fn derived_iv(&self, instr: &Instruction, basic: &[BasicIv]) -> Option<DerivedIv> {
+        let Instruction::Value { op, dest, args, .. } = instr else {
+            return None;
+        };
+        if args.len() != 2 || !self.defs.contains_key(dest) {
+            return None;
+        }
+        let is_basic = |var: &String| basic.iter().any(|iv| iv.var == *var);
+        let (iv, other) = if is_basic(&args[0]) {
+            (&args[0], &args[1])
+        } else if is_basic(&args[1]) {
+            (&args[1], &args[0])
+        } else {
+            return None;
+        };
+        let kind = match op {
+            ValueOps::Mul => DerivedKind::Mul(self.invariant(other)?),
+            ValueOps::Add if basic.iter().all(|b| b.next != *dest) => {
+                DerivedKind::Add(self.invariant(other)?)
+            }
+            ValueOps::PtrAdd if iv == &args[1] => match self.invariant(other)? {
+                Operand::Var(base) => DerivedKind::PtrAdd(base),
+                Operand::Const(_) => return None,
+            },
+            _ => return None,
+        };
+        Some(DerivedIv {
+            var: dest.clone(),
+            basic: iv.clone(),
+            kind,
+        })
+    }
+}
+
+/// Returns var as an operand if its value is the same in every iteration of l
+pub(crate) fn loop_invariant(cfg: &CFG, l: &Loop, var: &str) -> Option<Operand> {
+    Context {
+        cfg,
+        l,
+        defs: single_defs(cfg),
+    }
+    .invariant(var)
+}
+
+/// Finds the basic induction variables of a loop in ssa form from the phis of its header,
+/// and the values derived from them in its body
+pub fn find_induction_vars(cfg: &CFG, l: &Loop) -> InductionVars {
+    let context = Context {
+        cfg,
+        l,
+        defs: single_defs(cfg),
+    };
+    let basic: Vec<_> = cfg
+        .get_block(l.header)
+        .map(|block| {
+            block
+                .instrs
+                .iter()
+                .filter_map(|instr| context.basic_iv(instr))
+                .collect()
+        })
+        .unwrap_or_default();
+    let derived = l
+        .body
+        .iter()
+        .filter_map(|node| cfg.get_block(*node))
+        .flat_map(|block| &block.instrs)
+        .filter_map(|instr| context.derived_iv(instr, &basic))
+        .sorted_by(|a, b| a.var.cmp(&b.var))
+        .collect();
+    InductionVars { basic, derived }
+}
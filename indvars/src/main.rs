@@ -0,0 +1,77 @@
+use std::{env, io, process};
+
+use bril_rs::{load_program_from_read, output_program};
+use cfg::{generate_cfg, CFG};
+use indvars::{find_induction_vars, strength_reduce, DerivedKind, Operand};
+use loops::LoopUtil;
+use ssa::{convert_from_ssa, convert_to_ssa, SSAMode};
+
+fn format_operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Var(var) => var.clone(),
+        Operand::Const(c) => c.to_string(),
+    }
+}
+
+fn print_induction_vars(cfg: &CFG) {
+    println!("@{}", cfg.name);
+    let forest = cfg.find_loops();
+    for i in forest.preorder() {
+        let l = &forest.loops[i];
+        println!("  loop {}", cfg.get_block(l.header).unwrap().label);
+        let ivs = find_induction_vars(cfg, l);
+        for basic in &ivs.basic {
+            println!(
+                "    basic {} = {} + {} * k",
+                basic.var,
+                basic.init,
+                format_operand(&basic.step)
+            );
+        }
+        for derived in &ivs.derived {
+            let expr = match &derived.kind {
+                DerivedKind::Mul(factor) => {
+                    format!("{} * {}", derived.basic, format_operand(factor))
+                }
+                DerivedKind::Add(offset) => {
+                    format!("{} + {}", derived.basic, format_operand(offset))
+                }
+                DerivedKind::PtrAdd(base) => format!("ptradd {} {}", base, derived.basic),
+            };
+            println!("    derived {} = {}", derived.var, expr);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    // Prints the induction variables of every loop in ssa form instead of optimizing
+    let analyze = env::args().any(|arg| arg == "--analyze");
+
+    let mut program = load_program_from_read(io::stdin());
+    let mut removed = 0;
+    for function in &mut program.functions {
+        let cfg = generate_cfg(function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        let mut ssa_cfg = convert_to_ssa(&cfg, SSAMode::default());
+        if analyze {
+            print_induction_vars(&ssa_cfg);
+            continue;
+        }
+        let reduced = strength_reduce(&mut ssa_cfg).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        if reduced > 0 {
+            *function = convert_from_ssa(&ssa_cfg).to_function();
+        }
+        removed += reduced;
+    }
+
+    if !analyze {
+        eprintln!("mul ops removed: {}", removed);
+        output_program(&program);
+    }
+    Ok(())
+}
@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+
+use bril_rs::{ConstOps, Instruction, Literal, Type, ValueOps};
+use cfg::{CFGNode, CfgError, CFG};
+use dom::{DomResult, DominatorUtil};
+use loops::{insert_preheaders, Loop, LoopUtil};
+use util::SafeAccess;
+
+use crate::{
+    find_induction_vars, loop_invariant, single_defs, BasicIv, DerivedIv, DerivedKind, Operand,
+};
+
+/// Replaces multiplications of basic induction variables by invariants with a new induction
+/// variable updated by addition, then rewrites the loop tests of basic induction variables in
+/// terms of a reduced one and removes basic induction variables that are no longer used.
+/// Expects a cfg in ssa form, returns the number of removed mul instructions
+pub fn strength_reduce(cfg: &mut CFG) -> Result<usize, CfgError> {
+    let mut work = cfg.clone();
+    let mut forest = work.find_loops();
+    let preheaders = insert_preheaders(&mut work, &mut forest)?;
+    let mut namer = Namer::new(&work);
+    let dom = work.find_dominators();
+
+    let mut removed = 0;
+    for (l, &preheader) in forest.loops.iter().zip(&preheaders).rev() {
+        let ivs = find_induction_vars(&work, l);
+        let preheader_label = work.get_block(preheader).unwrap().label.clone();
+        let header = work.get_block(l.header).unwrap().label.clone();
+        let mut constants = Constants::new(&work, &dom, preheader);
+        let mut reduced = Vec::new();
+        for derived in &ivs.derived {
+            let DerivedKind::Mul(factor) = &derived.kind else {
+                continue;
+            };
+            let basic = ivs.basic.iter().find(|b| b.var == derived.basic).unwrap();
+            let iv = reduce_mul(
+                &mut work,
+                &mut namer,
+                &mut constants,
+                derived,
+                basic,
+                &preheader_label,
+                &header,
+            );
+            reduced.push((basic, iv, constants.resolve(factor)));
+        }
+        if reduced.is_empty() {
+            continue;
+        }
+        removed += reduced.len();
+        for basic in &ivs.basic {
+            let scaled = reduced.iter().find_map(|(b, iv, factor)| match factor {
+                Operand::Const(c) if b.var == basic.var && *c > 0 => Some((iv, *c)),
+                _ => None,
+            });
+            if let Some((iv, factor)) = scaled {
+                let mut tests = TestReplacement {
+                    namer: &mut namer,
+                    constants: &mut constants,
+                    scaled: iv,
+                    factor,
+                };
+                tests.replace(&mut work, l, basic, &preheader_label);
+            }
+            remove_if_dead(&mut work, basic);
+        }
+    }
+
+    if removed > 0 {
+        *cfg = work;
+    }
+    Ok(removed)
+}
+
+/// Hands out variable names that are not used in the function yet
+struct Namer {
+    used: HashSet<String>,
+}
+
+impl Namer {
+    fn new(cfg: &CFG) -> Self {
+        let used = cfg
+            .blocks
+            .iter()
+            .flat_map(|b| b.instrs.iter().flat_map(|instr| instr.get_dest()))
+            .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+            .collect();
+        Namer { used }
+    }
+
+    fn fresh(&mut self, base: &str) -> String {
+        let name = std::iter::once(base.to_string())
+            .chain((0..).map(|i| format!("{}.{}", base, i)))
+            .find(|name| !self.used.contains(name))
+            .unwrap();
+        self.used.insert(name.clone());
+        name
+    }
+}
+
+fn value(op: ValueOps, dest: &str, args: Vec<String>, labels: Vec<String>) -> Instruction {
+    Instruction::Value {
+        op,
+        dest: dest.to_string(),
+        args,
+        labels,
+        funcs: Vec::new(),
+        op_type: Type::Int,
+        pos: None,
+    }
+}
+
+fn constant(dest: &str, value: i64) -> Instruction {
+    Instruction::Constant {
+        op: ConstOps::Const,
+        dest: dest.to_string(),
+        const_type: Type::Int,
+        value: Literal::Int(value),
+        pos: None,
+    }
+}
+
+/// Integer constants whose definition reaches the end of a preheader, so they can be read
+/// there instead of being defined again
+struct Constants {
+    values: HashMap<String, i64>,
+    names: HashMap<i64, String>,
+}
+
+impl Constants {
+    fn new(cfg: &CFG, dom: &DomResult<CFGNode>, preheader: CFGNode) -> Self {
+        let defs = single_defs(cfg);
+        let mut constants = Constants {
+            values: HashMap::new(),
+            names: HashMap::new(),
+        };
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            if !dom.tree.dominates(CFGNode::Block(b), preheader) {
+                continue;
+            }
+            for instr in &block.instrs {
+                if let Instruction::Constant {
+                    dest,
+                    const_type: Type::Int,
+                    value: Literal::Int(value),
+                    ..
+                } = instr
+                {
+                    if defs.contains_key(dest) {
+                        constants.insert(dest, *value);
+                    }
+                }
+            }
+        }
+        constants
+    }
+
+    fn insert(&mut self, var: &str, value: i64) {
+        self.values.insert(var.to_string(), value);
+        self.names.entry(value).or_insert_with(|| var.to_string());
+    }
+
+    /// Replaces a variable holding a known constant with that constant
+    fn resolve(&self, operand: &Operand) -> Operand {
+        match operand {
+            Operand::Var(var) => self
+                .values
+                .get(var)
+                .map_or_else(|| operand.clone(), |&value| Operand::Const(value)),
+            Operand::Const(_) => operand.clone(),
+        }
+    }
+
+    /// Returns a variable holding value, defining one named base if there is none yet
+    fn name(
+        &mut self,
+        instrs: &mut Vec<Instruction>,
+        namer: &mut Namer,
+        base: &str,
+        value: i64,
+    ) -> String {
+        if let Some(var) = self.names.get(&value) {
+            return var.clone();
+        }
+        let var = namer.fresh(base);
+        instrs.push(constant(&var, value));
+        self.insert(&var, value);
+        var
+    }
+}
+
+/// Emits instructions computing a * b and returns the variable holding the product, which is
+/// an existing variable when the product is a known constant or one operand is 1
+fn emit_mul(
+    instrs: &mut Vec<Instruction>,
+    namer: &mut Namer,
+    constants: &mut Constants,
+    dest: &str,
+    a: &Operand,
+    b: &Operand,
+) -> String {
+    match (constants.resolve(a), constants.resolve(b)) {
+        (Operand::Const(a), Operand::Const(b)) => {
+            constants.name(instrs, namer, dest, a.wrapping_mul(b))
+        }
+        (Operand::Const(1), Operand::Var(var)) | (Operand::Var(var), Operand::Const(1)) => var,
+        (a, b) => {
+            let mut materialize = |operand: Operand| match operand {
+                Operand::Var(var) => var,
+                Operand::Const(c) => constants.name(instrs, namer, &format!("{}.factor", dest), c),
+            };
+            let args = vec![materialize(a), materialize(b)];
+            let dest = namer.fresh(dest);
+            instrs.push(value(ValueOps::Mul, &dest, args, Vec::new()));
+            dest
+        }
+    }
+}
+
+/// Rewrites j = i * c as a phi in the header starting at init * c and stepping by step * c
+/// next to the update of i, then redirects every use of j to the new phi, which is returned
+fn reduce_mul(
+    cfg: &mut CFG,
+    namer: &mut Namer,
+    constants: &mut Constants,
+    derived: &DerivedIv,
+    basic: &BasicIv,
+    preheader: &str,
+    header: &str,
+) -> String {
+    let DerivedKind::Mul(factor) = &derived.kind else {
+        return derived.var.clone();
+    };
+    let iv = namer.fresh(&format!("{}.iv", derived.var));
+    let next = namer.fresh(&format!("{}.next", derived.var));
+
+    let mut setup = Vec::new();
+    let init = emit_mul(
+        &mut setup,
+        namer,
+        constants,
+        &format!("{}.init", derived.var),
+        &Operand::Var(basic.init.clone()),
+        factor,
+    );
+    let step = emit_mul(
+        &mut setup,
+        namer,
+        constants,
+        &format!("{}.step", derived.var),
+        &basic.step,
+        factor,
+    );
+
+    for block in &mut cfg.blocks {
+        let mut index = 0;
+        while index < block.instrs.len() {
+            let instr = &mut block.instrs[index];
+            if instr.get_dest().as_deref() == Some(derived.var.as_str()) {
+                block.instrs.remove(index);
+                continue;
+            }
+            if let Some(args) = instr.get_args() {
+                if args.contains(&derived.var) {
+                    let renamed = args
+                        .into_iter()
+                        .map(|arg| if arg == derived.var { iv.clone() } else { arg })
+                        .collect();
+                    instr.set_args(renamed);
+                }
+            }
+            index += 1;
+            if instr_defines(&block.instrs[index - 1], &basic.next) {
+                let update = value(
+                    ValueOps::Add,
+                    &next,
+                    vec![iv.clone(), step.clone()],
+                    Vec::new(),
+                );
+                block.instrs.insert(index, update);
+                index += 1;
+            }
+        }
+
+        if block.label == header {
+            let (args, labels) = phi_args(&block.instrs, &basic.var, &init, &next, preheader);
+            block
+                .instrs
+                .insert(0, value(ValueOps::Phi, &iv, args, labels));
+        }
+        if block.label == preheader {
+            let end = block.instrs.len() - 1;
+            block.instrs.splice(end..end, setup.drain(..));
+        }
+    }
+    iv
+}
+
+/// Linear function test replacement: compares scaled, a reduced induction variable holding
+/// basic * factor, against bound * factor wherever basic is compared against an invariant bound.
+/// The factor is positive so the comparison keeps its outcome, as long as the scaled bound does
+/// not overflow
+struct TestReplacement<'a> {
+    namer: &'a mut Namer,
+    constants: &'a mut Constants,
+    scaled: &'a str,
+    factor: i64,
+}
+
+impl TestReplacement<'_> {
+    /// Rewrites the tests of basic, only when that leaves its update as its single other use
+    fn replace(&mut self, cfg: &mut CFG, l: &Loop, basic: &BasicIv, preheader: &str) {
+        let mut rewrites = Vec::new();
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                let Some(args) = instr.get_args().filter(|args| args.contains(&basic.var)) else {
+                    continue;
+                };
+                if instr_defines(instr, &basic.next) {
+                    continue;
+                }
+                let is_test = matches!(
+                    instr,
+                    Instruction::Value {
+                        op: ValueOps::Lt
+                            | ValueOps::Le
+                            | ValueOps::Gt
+                            | ValueOps::Ge
+                            | ValueOps::Eq,
+                        ..
+                    }
+                );
+                let bound = match args.as_slice() {
+                    [a, b] if is_test && *a == basic.var && *b != basic.var => {
+                        loop_invariant(cfg, l, b)
+                    }
+                    [a, b] if is_test && *b == basic.var && *a != basic.var => {
+                        loop_invariant(cfg, l, a)
+                    }
+                    _ => None,
+                };
+                let Some(bound) = bound else {
+                    return;
+                };
+                rewrites.push((b, i, bound));
+            }
+        }
+
+        let mut setup = Vec::new();
+        let mut bounds: Vec<(Operand, String)> = Vec::new();
+        for (b, i, bound) in rewrites {
+            let scaled_bound = match bounds.iter().find(|(operand, _)| *operand == bound) {
+                Some((_, var)) => var.clone(),
+                None => {
+                    let var = emit_mul(
+                        &mut setup,
+                        self.namer,
+                        self.constants,
+                        &format!("{}.bound", self.scaled),
+                        &bound,
+                        &Operand::Const(self.factor),
+                    );
+                    bounds.push((bound, var.clone()));
+                    var
+                }
+            };
+            let instr = &mut cfg.blocks[b].instrs[i];
+            let args = instr
+                .get_args()
+                .unwrap()
+                .into_iter()
+                .map(|arg| {
+                    if arg == basic.var {
+                        self.scaled.to_string()
+                    } else {
+                        scaled_bound.clone()
+                    }
+                })
+                .collect();
+            instr.set_args(args);
+        }
+
+        let block = cfg
+            .blocks
+            .iter_mut()
+            .find(|b| b.label == preheader)
+            .unwrap();
+        let end = block.instrs.len() - 1;
+        block.instrs.splice(end..end, setup);
+    }
+}
+
+fn instr_defines(instr: &Instruction, var: &str) -> bool {
+    instr.get_dest().as_deref() == Some(var)
+}
+
+/// Builds the arguments of the new phi from the phi of the basic induction variable,
+/// taking init from the preheader and next from every latch
+fn phi_args(
+    instrs: &[Instruction],
+    basic: &str,
+    init: &str,
+    next: &str,
+    preheader: &str,
+) -> (Vec<String>, Vec<String>) {
+    let labels = instrs
+        .iter()
+        .find_map(|instr| match instr {
+            Instruction::Value {
+                op: ValueOps::Phi,
+                dest,
+                labels,
+                ..
+            } if dest == basic => Some(labels.clone()),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let args = labels
+        .iter()
+        .map(|label| {
+            if label == preheader {
+                init.to_string()
+            } else {
+                next.to_string()
+            }
+        })
+        .collect();
+    (args, labels)
+}
+
+/// Removes a basic induction variable whose only use is its own update, returns whether it did
+fn remove_if_dead(cfg: &mut CFG, basic: &BasicIv) -> bool {
+    let users = |var: &str| -> Vec<Option<String>> {
+        cfg.blocks
+            .iter()
+            .flat_map(|b| &b.instrs)
+            .filter(|instr| {
+                instr
+                    .get_args()
+                    .is_some_and(|args| args.iter().any(|arg| arg == var))
+            })
+            .map(|instr| instr.get_dest())
+            .collect()
+    };
+    let only_updates = users(&basic.var)
+        .iter()
+        .all(|dest| dest.as_deref() == Some(basic.next.as_str()));
+    let only_loops_back = users(&basic.next)
+        .iter()
+        .all(|dest| dest.as_deref() == Some(basic.var.as_str()));
+    if only_updates && only_loops_back {
+        for block in &mut cfg.blocks {
+            block.instrs.retain(|instr| {
+                !instr_defines(instr, &basic.var) && !instr_defines(instr, &basic.next)
+            });
+        }
+    }
+    only_updates && only_loops_back
+}
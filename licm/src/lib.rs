@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+
+use bril_rs::{Instruction, ValueOps};
+use cfg::{CFGNode, CfgError, CFG};
+use df::{reaching_defs::ReachingDefs, run_worklist, DataFlowResult};
+use dom::{DomResult, DominatorUtil};
+use itertools::Itertools;
+use loops::{insert_preheaders, Loop, LoopUtil};
+use util::SafeAccess;
+
+/// A definition is identified by its block and its index within the block
+type Site = (usize, usize);
+
+/// Hoists loop invariant instructions of every loop into its preheader, innermost loops first,
+/// returns the number of hoisted instructions. Preheaders are only kept when something was hoisted
+pub fn licm(cfg: &mut CFG) -> Result<usize, CfgError> {
+    let mut work = cfg.clone();
+    let mut forest = work.find_loops();
+    let preheaders = insert_preheaders(&mut work, &mut forest)?;
+    let dom = work.find_dominators();
+
+    let mut hoisted = 0;
+    for (l, &preheader) in forest.loops.iter().zip(&preheaders).rev() {
+        let reaching = Reaching::new(&work);
+        let sites = hoistable(&work, &dom, &reaching, l);
+        hoisted += sites.len();
+        hoist(&mut work, &sites, preheader);
+    }
+
+    if hoisted > 0 {
+        *cfg = work;
+    }
+    Ok(hoisted)
+}
+
+/// Instruction level reaching definitions, built from the block level analysis
+struct Reaching {
+    result: DataFlowResult<ReachingDefs>,
+    /// last_def[(b, x)] is the index of the last definition of x in block b
+    last_def: HashMap<(usize, String), usize>,
+}
+
+impl Reaching {
+    fn new(cfg: &CFG) -> Self {
+        let mut last_def = HashMap::new();
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                if let Some(dest) = instr.get_dest() {
+                    last_def.insert((b, dest), i);
+                }
+            }
+        }
+        Reaching {
+            result: run_worklist::<ReachingDefs>(cfg),
+            last_def,
+        }
+    }
+
+    /// Returns the definitions of var that reach the instruction at index in block
+    fn defs(&self, cfg: &CFG, block: usize, index: usize, var: &str) -> HashSet<Site> {
+        let local = cfg.blocks[block].instrs[..index]
+            .iter()
+            .rposition(|instr| instr.get_dest().as_deref() == Some(var));
+        if let Some(i) = local {
+            return HashSet::from([(block, i)]);
+        }
+        self.result.in_map[&CFGNode::Block(block)]
+            .iter()
+            .filter(|def| def.variable == var)
+            .map(|def| {
+                (
+                    def.block_index,
+                    self.last_def[&(def.block_index, def.variable.clone())],
+                )
+            })
+            .collect()
+    }
+}
+
+/// Whether an instruction can run without side effects or traps wherever it is placed
+fn is_movable(instr: &Instruction) -> bool {
+    match instr {
+        Instruction::Constant { .. } => true,
+        Instruction::Value { op, .. } => !matches!(
+            op,
+            ValueOps::Div
+                | ValueOps::Int2char
+                | ValueOps::Call
+                | ValueOps::Phi
+                | ValueOps::Alloc
+                | ValueOps::Load
+                | ValueOps::PtrAdd
+        ),
+        Instruction::Effect { .. } => false,
+    }
+}
+
+fn body_sites(cfg: &CFG, l: &Loop) -> Vec<Site> {
+    l.body
+        .iter()
+        .filter_map(|node| match node {
+            CFGNode::Block(b) => Some(*b),
+            CFGNode::Return => None,
+        })
+        .sorted()
+        .flat_map(|b| (0..cfg.blocks[b].instrs.len()).map(move |i| (b, i)))
+        .collect()
+}
+
+fn in_loop(l: &Loop, (b, _): Site) -> bool {
+    l.body.contains(&CFGNode::Block(b))
+}
+
+/// Returns the instructions of the loop that can move to its preheader,
+/// in an order where definitions come before their uses
+fn hoistable(cfg: &CFG, dom: &DomResult<CFGNode>, reaching: &Reaching, l: &Loop) -> Vec<Site> {
+    let sites = body_sites(cfg, l);
+    let instr = |(b, i): Site| &cfg.blocks[b].instrs[i];
+    let arg_defs = |site: Site| -> Vec<HashSet<Site>> {
+        instr(site)
+            .get_args()
+            .unwrap_or_default()
+            .iter()
+            .map(|arg| reaching.defs(cfg, site.0, site.1, arg))
+            .collect()
+    };
+
+    // Operands are invariant when all their definitions are outside the loop,
+    // or when their only definition is an invariant instruction of the loop
+    let mut invariant = HashSet::new();
+    loop {
+        let before = invariant.len();
+        for &site in &sites {
+            if invariant.contains(&site) || !is_movable(instr(site)) {
+                continue;
+            }
+            let operands_invariant = arg_defs(site).iter().all(|defs| {
+                defs.iter().all(|&def| !in_loop(l, def))
+                    || (defs.len() == 1 && defs.iter().all(|def| invariant.contains(def)))
+            });
+            if operands_invariant {
+                invariant.insert(site);
+            }
+        }
+        if invariant.len() == before {
+            break;
+        }
+    }
+
+    let exiting: HashSet<_> = l.exits.iter().map(|(from, _)| *from).collect();
+    let safe = |site: Site| {
+        let dest = instr(site).get_dest().unwrap();
+        let node = CFGNode::Block(site.0);
+        let defs_in_loop = sites
+            .iter()
+            .filter(|&&s| instr(s).get_dest().as_ref() == Some(&dest))
+            .count();
+        let (inside_uses, outside_uses): (Vec<_>, Vec<_>) = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(b, block)| {
+                block
+                    .instrs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instr)| instr.get_args().is_some_and(|args| args.contains(&dest)))
+                    .map(move |(i, _)| (b, i))
+            })
+            .partition(|&use_site| in_loop(l, use_site));
+        let only_def_in_loop = defs_in_loop == 1
+            && inside_uses
+                .iter()
+                .all(|&(b, i)| reaching.defs(cfg, b, i, &dest) == HashSet::from([site]));
+        let dominates_exits = exiting.iter().all(|&exit| dom.tree.dominates(node, exit));
+        let dead_after_loop = outside_uses
+            .iter()
+            .all(|&(b, i)| !reaching.defs(cfg, b, i, &dest).contains(&site));
+        only_def_in_loop && (dominates_exits || dead_after_loop)
+    };
+
+    // An instruction can only move if the invariant definitions it reads move with it
+    let mut movable: HashSet<_> = invariant.iter().copied().filter(|&s| safe(s)).collect();
+    loop {
+        let before = movable.len();
+        let kept: HashSet<_> = movable
+            .iter()
+            .copied()
+            .filter(|&site| {
+                arg_defs(site)
+                    .iter()
+                    .flatten()
+                    .all(|&def| !in_loop(l, def) || movable.contains(&def))
+            })
+            .collect();
+        movable = kept;
+        if movable.len() == before {
+            break;
+        }
+    }
+
+    // A definition dominates its uses, so dominator tree depth orders them across blocks
+    sites
+        .into_iter()
+        .filter(|site| movable.contains(site))
+        .sorted_by_key(|&(b, i)| (dom.tree.depth(CFGNode::Block(b)), i))
+        .collect()
+}
+
+/// Moves the instructions at sites in order to the end of the preheader, before its jump
+fn hoist(cfg: &mut CFG, sites: &[Site], preheader: CFGNode) {
+    let moved: Vec<_> = sites
+        .iter()
+        .map(|&(b, i)| cfg.blocks[b].instrs[i].clone())
+        .collect();
+    let removed: HashSet<_> = sites.iter().copied().collect();
+    for (b, block) in cfg.blocks.iter_mut().enumerate() {
+        let mut i = 0;
+        block.instrs.retain(|_| {
+            i += 1;
+            !removed.contains(&(b, i - 1))
+        });
+    }
+    let block = cfg.get_block_mut(preheader).unwrap();
+    let end = block.instrs.len() - 1;
+    block.instrs.splice(end..end, moved);
+}
@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+
+use cfg::{CFGNode, CFG};
+use dom::{DomResult, DominatorUtil};
+use itertools::Itertools;
+use petgraph::{visit::Dfs, Direction::Incoming};
+
+pub use preheader::insert_preheaders;
+
+mod preheader;
+
+pub struct Loop {
+    /// header is the only block of the loop that can be entered from outside of it
+    pub header: CFGNode,
+    /// body contains every block of the loop, including the header and nested loops
+    pub body: HashSet<CFGNode>,
+    /// latches are the blocks with a back edge to the header
+    pub latches: HashSet<CFGNode>,
+    /// exits are the edges from a block of the body to a node outside of it
+    pub exits: HashSet<(CFGNode, CFGNode)>,
+    /// parent is the index of the innermost loop containing this one
+    pub parent: Option<usize>,
+    /// children are the indices of the loops immediately nested in this one
+    pub children: Vec<usize>,
+    /// depth is 1 for outermost loops
+    pub depth: usize,
+}
+
+pub struct LoopForest {
+    /// loops are ordered so that every loop comes before the loops nested in it
+    pub loops: Vec<Loop>,
+    /// innermost[x] is the index of the innermost loop containing x
+    pub innermost: HashMap<CFGNode, usize>,
+}
+
+impl LoopForest {
+    /// Finds the natural loops of a cfg, merging back edges that share a header into one loop
+    pub fn new(cfg: &CFG, dom: &DomResult<CFGNode>) -> Self {
+        let mut reachable = HashSet::new();
+        let mut dfs = Dfs::new(&cfg.graph, CFGNode::Block(0));
+        while let Some(node) = dfs.next(&cfg.graph) {
+            reachable.insert(node);
+        }
+
+        // An edge is a back edge when its target dominates its source
+        let mut back_edges: HashMap<_, HashSet<_>> = HashMap::new();
+        for (from, to, _) in cfg.graph.all_edges() {
+            if dom.tree.dominates(to, from) {
+                back_edges.entry(to).or_default().insert(from);
+            }
+        }
+
+        let mut loops: Vec<_> = back_edges
+            .into_iter()
+            .map(|(header, latches)| {
+                let body = natural_loop_body(cfg, &reachable, header, &latches);
+                let exits = body
+                    .iter()
+                    .flat_map(|&node| cfg.graph.neighbors(node).map(move |succ| (node, succ)))
+                    .filter(|(_, succ)| !body.contains(succ))
+                    .collect();
+                Loop {
+                    header,
+                    body,
+                    latches,
+                    exits,
+                    parent: None,
+                    children: Vec::new(),
+                    depth: 1,
+                }
+            })
+            .collect();
+
+        // Loops with different headers are either disjoint or nested, so sorting by size
+        // puts the innermost loop containing a header last among the loops that contain it
+        loops.sort_by_key(|l| (std::cmp::Reverse(l.body.len()), l.header));
+        for i in 0..loops.len() {
+            let header = loops[i].header;
+            if let Some(parent) = (0..i).rev().find(|&j| loops[j].body.contains(&header)) {
+                loops[i].parent = Some(parent);
+                loops[i].depth = loops[parent].depth + 1;
+                loops[parent].children.push(i);
+            }
+        }
+
+        let mut innermost = HashMap::new();
+        for (i, l) in loops.iter().enumerate() {
+            for &node in &l.body {
+                innermost.insert(node, i);
+            }
+        }
+
+        LoopForest { loops, innermost }
+    }
+
+    /// Returns the number of loops containing node, 0 if it is not in a loop
+    pub fn depth(&self, node: CFGNode) -> usize {
+        self.innermost
+            .get(&node)
+            .map_or(0, |&i| self.loops[i].depth)
+    }
+
+    /// Returns the index of the loop headed by node
+    pub fn loop_with_header(&self, node: CFGNode) -> Option<usize> {
+        self.loops.iter().position(|l| l.header == node)
+    }
+
+    /// Returns the indices of the outermost loops
+    pub fn roots(&self) -> Vec<usize> {
+        (0..self.loops.len())
+            .filter(|&i| self.loops[i].parent.is_none())
+            .collect()
+    }
+
+    /// Returns the loop indices in a preorder walk of the forest, children sorted by header
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut stack: Vec<_> = self
+            .roots()
+            .into_iter()
+            .sorted_by_key(|&i| std::cmp::Reverse(self.loops[i].header))
+            .collect();
+        while let Some(i) = stack.pop() {
+            result.push(i);
+            stack.extend(
+                self.loops[i]
+                    .children
+                    .iter()
+                    .copied()
+                    .sorted_by_key(|&c| std::cmp::Reverse(self.loops[c].header)),
+            );
+        }
+        result
+    }
+}
+
+/// Collects the header and every reachable block that reaches a latch without passing the header
+fn natural_loop_body(
+    cfg: &CFG,
+    reachable: &HashSet<CFGNode>,
+    header: CFGNode,
+    latches: &HashSet<CFGNode>,
+) -> HashSet<CFGNode> {
+    let mut body = HashSet::from([header]);
+    let mut stack: Vec<_> = latches.iter().copied().collect();
+    while let Some(node) = stack.pop() {
+        if body.insert(node) {
+            stack.extend(
+                cfg.graph
+                    .neighbors_directed(node, Incoming)
+                    .filter(|pred| reachable.contains(pred)),
+            );
+        }
+    }
+    body
+}
+
+pub trait LoopUtil {
+    fn find_loops(&self) -> LoopForest;
+}
+
+impl LoopUtil for CFG {
+    fn find_loops(&self) -> LoopForest {
+        LoopForest::new(self, &self.find_dominators())
+    }
+}
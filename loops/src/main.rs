@@ -0,0 +1,79 @@
+use std::{collections::HashSet, env, io, process};
+
+use bril_rs::{load_program_from_read, output_program};
+use cfg::{generate_cfg, CFGNode, Dot, CFG};
+use itertools::Itertools;
+use loops::{insert_preheaders, LoopForest, LoopUtil};
+
+fn node_name(node: CFGNode, cfg: &CFG) -> String {
+    match node {
+        CFGNode::Block(i) => cfg.blocks[i].label.clone(),
+        CFGNode::Return => "return".to_string(),
+    }
+}
+
+fn format_nodes(nodes: &HashSet<CFGNode>, cfg: &CFG) -> String {
+    format!(
+        "{{ {} }}",
+        nodes.iter().sorted().map(|&n| node_name(n, cfg)).join(", ")
+    )
+}
+
+fn print_loops(forest: &LoopForest, cfg: &CFG) {
+    println!("@{}", cfg.name);
+    for i in forest.preorder() {
+        let l = &forest.loops[i];
+        let indent = "  ".repeat(l.depth);
+        let exits = l
+            .exits
+            .iter()
+            .sorted()
+            .map(|&(from, to)| format!("{} -> {}", node_name(from, cfg), node_name(to, cfg)))
+            .join(", ");
+        println!(
+            "{}loop {} (depth {})",
+            indent,
+            node_name(l.header, cfg),
+            l.depth
+        );
+        println!("{}  body: {}", indent, format_nodes(&l.body, cfg));
+        println!("{}  latches: {}", indent, format_nodes(&l.latches, cfg));
+        println!("{}  exits: {{ {} }}", indent, exits);
+    }
+}
+
+fn main() -> io::Result<()> {
+    let dot_output = env::args().any(|arg| arg == "--dot");
+    let preheaders = env::args().any(|arg| arg == "--preheaders");
+
+    let mut program = load_program_from_read(io::stdin());
+    let mut dot = Dot::new();
+    for function in &mut program.functions {
+        let mut cfg = generate_cfg(function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        let mut forest = cfg.find_loops();
+        if preheaders {
+            insert_preheaders(&mut cfg, &mut forest).unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            });
+            *function = cfg.to_function();
+        } else if dot_output {
+            dot.add_cfg(&cfg, |node| match forest.depth(node) {
+                0 => Vec::new(),
+                depth => vec![format!("loop depth: {}", depth)],
+            });
+        } else {
+            print_loops(&forest, &cfg);
+        }
+    }
+
+    if preheaders {
+        output_program(&program);
+    } else if dot_output {
+        print!("{}", dot);
+    }
+    Ok(())
+}
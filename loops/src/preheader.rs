@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+
+use bbb::Block;
+use bril_rs::{EffectOps, Instruction, ValueOps};
+use cfg::{CFGNode, CfgError, CFG};
+use itertools::Itertools;
+use petgraph::Direction::Incoming;
+use util::SafeAccess;
+
+use crate::LoopForest;
+
+/// Gives every loop a preheader, a block outside the loop that is the only predecessor of the
+/// header from outside and whose only successor is the header. Existing blocks that already
+/// qualify are reused. Returns the preheader of every loop, indexed like forest.loops
+pub fn insert_preheaders(cfg: &mut CFG, forest: &mut LoopForest) -> Result<Vec<CFGNode>, CfgError> {
+    let mut vars: HashSet<_> = cfg
+        .blocks
+        .iter()
+        .flat_map(|b| b.instrs.iter().flat_map(|instr| instr.get_dest()))
+        .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+        .collect();
+
+    let mut preheaders = Vec::new();
+    for i in 0..forest.loops.len() {
+        let header = forest.loops[i].header;
+        let outside_preds: Vec<_> = cfg
+            .graph
+            .neighbors_directed(header, Incoming)
+            .filter(|pred| !forest.loops[i].body.contains(pred))
+            .sorted()
+            .collect();
+        if let [pred] = outside_preds.as_slice() {
+            if cfg.graph.neighbors(*pred).count() == 1 {
+                preheaders.push(*pred);
+                continue;
+            }
+        }
+
+        let header_label = cfg.get_block(header).unwrap().label.clone();
+        let label = cfg.fresh_label(&format!("{}.preheader", header_label));
+        let pred_labels: HashSet<_> = outside_preds
+            .iter()
+            .map(|&pred| cfg.get_block(pred).unwrap().label.clone())
+            .collect();
+
+        // Phi arguments from outside the loop now arrive through the preheader
+        let mut instrs = Vec::new();
+        for instr in &mut cfg.get_block_mut(header).unwrap().instrs {
+            if let Instruction::Value {
+                op: ValueOps::Phi,
+                args,
+                labels,
+                dest,
+                op_type,
+                ..
+            } = instr
+            {
+                let (outside, inside): (Vec<_>, Vec<_>) = args
+                    .iter()
+                    .cloned()
+                    .zip(labels.iter().cloned())
+                    .partition(|(_, l)| pred_labels.contains(l));
+                if outside.is_empty() {
+                    continue;
+                }
+                let arg = if outside.iter().map(|(arg, _)| arg).all_equal() {
+                    outside[0].0.clone()
+                } else {
+                    let var = fresh_var(&mut vars, dest);
+                    let (pre_args, pre_labels) = outside.into_iter().unzip();
+                    instrs.push(Instruction::Value {
+                        op: ValueOps::Phi,
+                        args: pre_args,
+                        labels: pre_labels,
+                        dest: var.clone(),
+                        funcs: Vec::new(),
+                        op_type: op_type.clone(),
+                        pos: None,
+                    });
+                    var
+                };
+                let (new_args, new_labels) = inside
+                    .into_iter()
+                    .chain(std::iter::once((arg, label.clone())))
+                    .unzip();
+                *args = new_args;
+                *labels = new_labels;
+            }
+        }
+        instrs.push(Instruction::Effect {
+            op: EffectOps::Jump,
+            labels: vec![header_label.clone()],
+            args: Vec::new(),
+            funcs: Vec::new(),
+            pos: None,
+        });
+
+        for &pred in &outside_preds {
+            if let Some(Instruction::Effect { labels, .. }) =
+                cfg.get_block_mut(pred).unwrap().instrs.last_mut()
+            {
+                labels
+                    .iter_mut()
+                    .filter(|l| **l == header_label)
+                    .for_each(|l| *l = label.clone());
+            }
+        }
+
+        let preheader = CFGNode::Block(cfg.blocks.len());
+        cfg.blocks.push(Block { label, instrs });
+        cfg.rebuild_graph()?;
+
+        // The preheader belongs to every loop enclosing this one,
+        // and takes over the edges that left other loops for the header
+        let mut parent = forest.loops[i].parent;
+        if let Some(p) = parent {
+            forest.innermost.insert(preheader, p);
+        }
+        while let Some(p) = parent {
+            forest.loops[p].body.insert(preheader);
+            parent = forest.loops[p].parent;
+        }
+        for l in &mut forest.loops {
+            let retargeted: Vec<_> = l
+                .exits
+                .iter()
+                .filter(|(from, to)| *to == header && outside_preds.contains(from))
+                .copied()
+                .collect();
+            for (from, to) in retargeted {
+                l.exits.remove(&(from, to));
+                l.exits.insert((from, preheader));
+            }
+        }
+        preheaders.push(preheader);
+    }
+    Ok(preheaders)
+}
+
+/// Returns a variable name starting with base that is not defined yet, and reserves it
+fn fresh_var(vars: &mut HashSet<String>, base: &str) -> String {
+    let var = (0..)
+        .map(|i| format!("{}.pre.{}", base, i))
+        .find(|var| !vars.contains(var))
+        .unwrap();
+    vars.insert(var.clone());
+    var
+}
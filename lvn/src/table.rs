@@ -1,10 +1,11 @@
 use std::collections::{hash_map::Entry, HashMap};
 
 use bril_rs::{Instruction, Literal, Type, ValueOps};
+use callgraph::Summaries;
 use itertools::Itertools;
 
-use crate::value::Value;
 use bril_rs::Literal::*;
+use util::value::Value;
 
 #[derive(Debug, Clone)]
 struct TableEntry {
@@ -12,18 +13,20 @@ struct TableEntry {
     variables: Vec<String>,
 }
 
-pub struct Table {
+pub struct Table<'a> {
     entries: Vec<TableEntry>,
     value_index: HashMap<Value, usize>,
     cloud: HashMap<String, usize>,
+    summaries: &'a Summaries,
 }
 
-impl Table {
-    pub fn new() -> Self {
+impl<'a> Table<'a> {
+    pub fn new(summaries: &'a Summaries) -> Self {
         Table {
             entries: Vec::new(),
             value_index: HashMap::new(),
             cloud: HashMap::new(),
+            summaries,
         }
     }
 
@@ -76,6 +79,12 @@ impl Table {
         None
     }
 
+    /// Stops value from matching later computations, whose result may differ from the
+    /// variables already holding it
+    pub fn forget(&mut self, value: &Value) {
+        self.value_index.remove(value);
+    }
+
     /// Adds mapping between given variable and value
     pub fn add_binding(&mut self, variable: &str, value: &Value) {
         let index = self.value_index.get(value).unwrap();
@@ -206,6 +215,21 @@ impl Table {
                 ..
             } => Some(Value::Constant(Literal::Float(value as f64))),
             Instruction::Constant { value, .. } => Some(Value::Constant(value)),
+            Instruction::Value {
+                op: ValueOps::Call,
+                args,
+                funcs,
+                op_type,
+                ..
+            } if self.summaries.is_pure_call(instr) => Some(Value::Call {
+                kind: op_type,
+                func: funcs[0].clone(),
+                args: args
+                    .iter()
+                    .map(|arg| self.cloud.get(arg).unwrap())
+                    .copied()
+                    .collect(),
+            }),
             Instruction::Value {
                 op: ValueOps::Alloc | ValueOps::Call,
                 dest,
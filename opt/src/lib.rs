@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use bril_rs::{Function, Program};
+use callgraph::Summaries;
+use cfg::CfgError;
+
+pub use passes::{find_pass, registry};
+
+mod passes;
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// Runs the pass on a single function, returns whether the function changed.
+    /// summaries describe the functions of the program as they were before the pass
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError>;
+
+    /// Runs the pass on every function of the program, returns whether the program changed
+    fn run(&self, program: &mut Program) -> Result<bool, CfgError> {
+        let summaries = Summaries::new(program);
+        let mut dirty = false;
+        for function in &mut program.functions {
+            dirty |= self.run_function(function, &summaries)?;
+        }
+        Ok(dirty)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PassTiming {
+    pub runs: usize,
+    pub total: Duration,
+}
+
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    timings: Vec<PassTiming>,
+    /// Maximum number of times the pipeline is repeated, 1 disables fixpoint repetition.
+    /// The pipeline always runs at least once
+    pub max_iterations: usize,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            timings: Vec::new(),
+            max_iterations: 1,
+        }
+    }
+
+    /// Builds a pipeline from a comma separated list of pass names, e.g. "lvn,dce,ssa"
+    pub fn from_pipeline(pipeline: &str) -> Result<Self, String> {
+        let mut manager = PassManager::new();
+        for name in pipeline.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let pass = find_pass(name).ok_or_else(|| format!("Unknown pass: {}", name))?;
+            manager.add_pass(pass);
+        }
+        Ok(manager)
+    }
+
+    pub fn add_pass(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+        self.timings.push(PassTiming::default());
+    }
+
+    /// Runs the pipeline until no pass changes the program or max_iterations is reached,
+    /// returns the number of pipeline iterations performed
+    pub fn run(&mut self, program: &mut Program) -> Result<usize, CfgError> {
+        let mut iterations = 0;
+        loop {
+            iterations += 1;
+            let mut dirty = false;
+            for (pass, timing) in self.passes.iter().zip(self.timings.iter_mut()) {
+                let start = Instant::now();
+                dirty |= pass.run(program)?;
+                timing.total += start.elapsed();
+                timing.runs += 1;
+            }
+            if !dirty || iterations >= self.max_iterations {
+                break;
+            }
+        }
+        Ok(iterations)
+    }
+
+    pub fn timings(&self) -> impl Iterator<Item = (&'static str, &PassTiming)> {
+        self.passes
+            .iter()
+            .map(|pass| pass.name())
+            .zip(self.timings.iter())
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
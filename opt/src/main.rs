@@ -0,0 +1,74 @@
+use std::{env, io, process};
+
+use bril_rs::{load_program_from_read, output_program};
+use opt::{registry, PassManager};
+
+const USAGE: &str = "usage: opt -p <pass>[,<pass>...] [--fixpoint [max_iterations]] [--time]
+       opt --list";
+
+/// Default iteration cap for --fixpoint, since passes like into-ssa never converge
+const DEFAULT_MAX_ITERATIONS: usize = 10;
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn main() -> io::Result<()> {
+    let mut pipeline = None;
+    let mut max_iterations = 1;
+    let mut time = false;
+
+    let mut args = env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" | "--passes" => {
+                pipeline = Some(
+                    args.next()
+                        .unwrap_or_else(|| exit_with("Missing pass list")),
+                );
+            }
+            "-f" | "--fixpoint" => {
+                max_iterations = match args.peek().map(|n| n.parse::<usize>()) {
+                    Some(Ok(0)) => exit_with("The iteration cap must be at least 1"),
+                    Some(Ok(n)) => {
+                        args.next();
+                        n
+                    }
+                    _ => DEFAULT_MAX_ITERATIONS,
+                };
+            }
+            "-t" | "--time" => time = true,
+            "-l" | "--list" => {
+                for pass in registry() {
+                    println!("{:<10} {}", pass.name(), pass.description());
+                }
+                return Ok(());
+            }
+            _ => exit_with(&format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let pipeline = pipeline.unwrap_or_else(|| exit_with("No passes given"));
+    let mut manager = PassManager::from_pipeline(&pipeline).unwrap_or_else(|e| exit_with(&e));
+    manager.max_iterations = max_iterations;
+
+    let mut program = load_program_from_read(io::stdin());
+    let iterations = manager.run(&mut program).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+    output_program(&program);
+
+    if time {
+        for (name, timing) in manager.timings() {
+            eprintln!(
+                "{:<10} {:>10.3?} ({} runs)",
+                name, timing.total, timing.runs
+            );
+        }
+        eprintln!("pipeline iterations: {}", iterations);
+    }
+    Ok(())
+}
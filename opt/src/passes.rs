@@ -0,0 +1,415 @@
+use alias::PointsTo;
+use bril_rs::{Code, Function, Instruction, Program};
+use callgraph::Summaries;
+use cfg::{generate_cfg, CfgError};
+use cse::global_cse;
+use dce::{aggressive_dce, global_dce, regular_dce, run_dce, trivial_dce};
+use indvars::strength_reduce;
+use licm::licm;
+use lvn::apply_lvn;
+use pre::pre;
+use sccp::sccp;
+use simplify::simplify_cfg;
+use ssa::{convert_from_ssa, convert_to_ssa, SSAMode};
+
+use crate::Pass;
+
+/// Returns every registered pass, in the order they are listed to users
+pub fn registry() -> Vec<Box<dyn Pass>> {
+    vec![
+        Box::new(Lvn),
+        Box::new(TrivialDce),
+        Box::new(RegularDce),
+        Box::new(Dce),
+        Box::new(GlobalDce),
+        Box::new(AggressiveDce),
+        Box::new(SimplifyCfg),
+        Box::new(Licm),
+        Box::new(StrengthReduce),
+        Box::new(Sccp),
+        Box::new(Cse),
+        Box::new(Pre),
+        Box::new(IntoSsa),
+        Box::new(FromSsa),
+        Box::new(Ssa),
+    ]
+}
+
+pub fn find_pass(name: &str) -> Option<Box<dyn Pass>> {
+    registry().into_iter().find(|pass| pass.name() == name)
+}
+
+fn instructions(function: &Function) -> Vec<&Instruction> {
+    function
+        .instrs
+        .iter()
+        .filter_map(|code| match code {
+            Code::Instruction(instr) => Some(instr),
+            Code::Label { .. } => None,
+        })
+        .collect()
+}
+
+/// Applies f to function, returns whether the instructions changed.
+/// Labels are ignored since rebuilding code from blocks renames them.
+fn track_changes(
+    function: &mut Function,
+    f: impl FnOnce(&mut Function) -> Result<(), CfgError>,
+) -> Result<bool, CfgError> {
+    let before = function.clone();
+    f(function)?;
+    Ok(instructions(function) != instructions(&before))
+}
+
+struct Lvn;
+
+impl Pass for Lvn {
+    fn name(&self) -> &'static str {
+        "lvn"
+    }
+
+    fn description(&self) -> &'static str {
+        "local value numbering"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        track_changes(function, |function| {
+            apply_lvn(function, summaries, None);
+            Ok(())
+        })
+    }
+
+    /// Loads are only kept across stores that points-to analysis of the whole program
+    /// shows cannot write what they read
+    fn run(&self, program: &mut Program) -> Result<bool, CfgError> {
+        let summaries = Summaries::new(program);
+        let points_to = PointsTo::analyze(program);
+        let mut dirty = false;
+        for function in &mut program.functions {
+            dirty |= track_changes(function, |function| {
+                apply_lvn(function, &summaries, Some(&points_to));
+                Ok(())
+            })?;
+        }
+        Ok(dirty)
+    }
+}
+
+struct TrivialDce;
+
+impl Pass for TrivialDce {
+    fn name(&self) -> &'static str {
+        "tdce"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove instructions whose destination is never used"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        Ok(trivial_dce(function, summaries))
+    }
+}
+
+struct RegularDce;
+
+impl Pass for RegularDce {
+    fn name(&self) -> &'static str {
+        "rdce"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove definitions overwritten before use within a block"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        Ok(regular_dce(function, summaries))
+    }
+}
+
+struct Dce;
+
+impl Pass for Dce {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn description(&self) -> &'static str {
+        "tdce and rdce repeated until convergence"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        Ok(run_dce(function, summaries))
+    }
+}
+
+struct GlobalDce;
+
+impl Pass for GlobalDce {
+    fn name(&self) -> &'static str {
+        "gdce"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove definitions that are not live, using global liveness"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        global_dce(function, summaries)
+    }
+}
+
+struct AggressiveDce;
+
+impl Pass for AggressiveDce {
+    fn name(&self) -> &'static str {
+        "adce"
+    }
+
+    fn description(&self) -> &'static str {
+        "mark and sweep dce from prints, stores, returns and calls with effects"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        Ok(aggressive_dce(function, summaries))
+    }
+}
+
+struct IntoSsa;
+
+impl Pass for IntoSsa {
+    fn name(&self) -> &'static str {
+        "into-ssa"
+    }
+
+    fn description(&self) -> &'static str {
+        "convert into ssa form"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        track_changes(function, |function| {
+            *function = convert_to_ssa(&generate_cfg(function)?, SSAMode::default()).to_function();
+            Ok(())
+        })
+    }
+}
+
+struct FromSsa;
+
+impl Pass for FromSsa {
+    fn name(&self) -> &'static str {
+        "from-ssa"
+    }
+
+    fn description(&self) -> &'static str {
+        "replace phi nodes with copies"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        track_changes(function, |function| {
+            *function = convert_from_ssa(&generate_cfg(function)?).to_function();
+            Ok(())
+        })
+    }
+}
+
+struct Ssa;
+
+impl Pass for Ssa {
+    fn name(&self) -> &'static str {
+        "ssa"
+    }
+
+    fn description(&self) -> &'static str {
+        "convert into ssa form and back out"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        track_changes(function, |function| {
+            let ssa_cfg = convert_to_ssa(&generate_cfg(function)?, SSAMode::default());
+            *function = convert_from_ssa(&ssa_cfg).to_function();
+            Ok(())
+        })
+    }
+}
+
+struct SimplifyCfg;
+
+impl Pass for SimplifyCfg {
+    fn name(&self) -> &'static str {
+        "simplifycfg"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove unreachable blocks, merge blocks and thread jumps"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        track_changes(function, |function| {
+            let mut cfg = generate_cfg(function)?;
+            simplify_cfg(&mut cfg)?;
+            *function = cfg.to_function();
+            Ok(())
+        })
+    }
+}
+
+struct Licm;
+
+impl Pass for Licm {
+    fn name(&self) -> &'static str {
+        "licm"
+    }
+
+    fn description(&self) -> &'static str {
+        "hoist loop invariant instructions into loop preheaders"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        let mut cfg = generate_cfg(function)?;
+        if licm(&mut cfg)? == 0 {
+            return Ok(false);
+        }
+        *function = cfg.to_function();
+        Ok(true)
+    }
+}
+
+struct StrengthReduce;
+
+impl Pass for StrengthReduce {
+    fn name(&self) -> &'static str {
+        "strength-reduce"
+    }
+
+    fn description(&self) -> &'static str {
+        "replace induction variable multiplications with additions, expects ssa form"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        let mut cfg = generate_cfg(function)?;
+        if strength_reduce(&mut cfg)? == 0 {
+            return Ok(false);
+        }
+        *function = cfg.to_function();
+        Ok(true)
+    }
+}
+
+struct Sccp;
+
+impl Pass for Sccp {
+    fn name(&self) -> &'static str {
+        "sccp"
+    }
+
+    fn description(&self) -> &'static str {
+        "sparse conditional constant propagation, expects ssa form"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        let mut cfg = generate_cfg(function)?;
+        if !sccp(&mut cfg)? {
+            return Ok(false);
+        }
+        *function = cfg.to_function();
+        Ok(true)
+    }
+}
+
+struct Cse;
+
+impl Pass for Cse {
+    fn name(&self) -> &'static str {
+        "cse"
+    }
+
+    fn description(&self) -> &'static str {
+        "global common subexpression elimination using available expressions"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        global_cse(function, summaries)
+    }
+}
+
+struct Pre;
+
+impl Pass for Pre {
+    fn name(&self) -> &'static str {
+        "pre"
+    }
+
+    fn description(&self) -> &'static str {
+        "partial redundancy elimination by lazy code motion"
+    }
+
+    fn run_function(
+        &self,
+        function: &mut Function,
+        _summaries: &Summaries,
+    ) -> Result<bool, CfgError> {
+        let mut cfg = generate_cfg(function)?;
+        if !pre(&mut cfg)? {
+            return Ok(false);
+        }
+        *function = cfg.to_function();
+        Ok(true)
+    }
+}
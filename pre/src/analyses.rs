@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+
+use bril_rs::Instruction;
+use cfg::{CFGNode, CFG};
+use df::{available_exprs::Expression, Analysis, DataFlowDisplay, Direction};
+use itertools::Itertools;
+use util::SafeAccess;
+
+/// A set of expressions where None stands for every expression, the identity of intersection
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ExprSet(Option<HashSet<Expression>>);
+
+impl ExprSet {
+    pub fn empty() -> Self {
+        ExprSet(Some(HashSet::new()))
+    }
+
+    /// Returns the expressions, treating the set of every expression as empty.
+    /// Only nodes the analysis never reached keep that set
+    pub fn to_set(&self) -> HashSet<Expression> {
+        self.0.clone().unwrap_or_default()
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (None, _) => other.clone(),
+            (_, None) => self.clone(),
+            (Some(a), Some(b)) => ExprSet(Some(a.intersection(b).cloned().collect())),
+        }
+    }
+
+    pub fn union(&self, other: &HashSet<Expression>) -> Self {
+        match &self.0 {
+            None => self.clone(),
+            Some(exprs) => ExprSet(Some(exprs.union(other).cloned().collect())),
+        }
+    }
+
+    /// Removes every expression reading the destination of instr
+    fn kill(&mut self, instr: &Instruction) {
+        if let (Some(exprs), Some(dest)) = (&mut self.0, instr.get_dest()) {
+            exprs.retain(|expr| !expr.uses(&dest));
+        }
+    }
+
+    fn insert(&mut self, instr: &Instruction) {
+        if let (Some(exprs), Some(expr)) = (&mut self.0, Expression::from_instr(instr)) {
+            exprs.insert(expr);
+        }
+    }
+
+    fn remove(&mut self, instr: &Instruction) {
+        if let (Some(exprs), Some(expr)) = (&mut self.0, Expression::from_instr(instr)) {
+            exprs.remove(&expr);
+        }
+    }
+}
+
+impl DataFlowDisplay for ExprSet {
+    fn generate_string(&self, _cfg: &CFG) -> String {
+        match &self.0 {
+            None => "⊤".to_string(),
+            Some(exprs) if exprs.is_empty() => "∅".to_string(),
+            Some(exprs) => exprs
+                .iter()
+                .map(|expr| format!("({})", expr))
+                .sorted()
+                .join(" "),
+        }
+    }
+}
+
+/// Expressions computed on every path from a point before any of their arguments change
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Anticipated(pub ExprSet);
+
+impl Analysis for Anticipated {
+    fn direction() -> Direction {
+        Direction::Backward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        Anticipated(ExprSet::default())
+    }
+
+    fn bottom(_cfg: &CFG) -> Self {
+        Anticipated(ExprSet::empty())
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        Anticipated(self.0.intersection(&other.0))
+    }
+
+    fn boundary(cfg: &CFG) -> Self {
+        Self::bottom(cfg)
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut exprs = self.0.clone();
+        exprs.kill(instr);
+        exprs.insert(instr);
+        Anticipated(exprs)
+    }
+}
+
+impl DataFlowDisplay for Anticipated {
+    fn generate_string(&self, cfg: &CFG) -> String {
+        self.0.generate_string(cfg)
+    }
+}
+
+/// Expressions that are either computed on every path to a point or would be if every
+/// anticipated expression were computed as early as possible.
+/// The expressions anticipated at the start of a node are added before its instructions
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Available(pub ExprSet);
+
+impl Analysis for Available {
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        Available(ExprSet::default())
+    }
+
+    fn bottom(_cfg: &CFG) -> Self {
+        Available(ExprSet::empty())
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        Available(self.0.intersection(&other.0))
+    }
+
+    fn boundary(cfg: &CFG) -> Self {
+        Self::bottom(cfg)
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut exprs = self.0.clone();
+        exprs.kill(instr);
+        Available(exprs)
+    }
+}
+
+impl DataFlowDisplay for Available {
+    fn generate_string(&self, cfg: &CFG) -> String {
+        self.0.generate_string(cfg)
+    }
+}
+
+/// Expressions whose earliest placement can be delayed to a point without passing a use.
+/// The earliest expressions of a node are added before its instructions
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Postponable(pub ExprSet);
+
+impl Analysis for Postponable {
+    fn direction() -> Direction {
+        Direction::Forward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        Postponable(ExprSet::default())
+    }
+
+    fn bottom(_cfg: &CFG) -> Self {
+        Postponable(ExprSet::empty())
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        Postponable(self.0.intersection(&other.0))
+    }
+
+    fn boundary(cfg: &CFG) -> Self {
+        Self::bottom(cfg)
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut exprs = self.0.clone();
+        exprs.remove(instr);
+        Postponable(exprs)
+    }
+}
+
+impl DataFlowDisplay for Postponable {
+    fn generate_string(&self, cfg: &CFG) -> String {
+        self.0.generate_string(cfg)
+    }
+}
+
+/// Expressions whose value is read on some path from a point before being placed again.
+/// The latest expressions of a node are removed after its instructions
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Used(pub HashSet<Expression>);
+
+impl Analysis for Used {
+    fn direction() -> Direction {
+        Direction::Backward
+    }
+
+    fn top(_cfg: &CFG) -> Self {
+        Used(HashSet::new())
+    }
+
+    fn bottom(cfg: &CFG) -> Self {
+        let exprs = cfg
+            .blocks
+            .iter()
+            .flat_map(|block| block.instrs.iter().flat_map(Expression::from_instr))
+            .collect();
+        Used(exprs)
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        Used(self.0.union(&other.0).cloned().collect())
+    }
+
+    fn transfer_instr(&self, instr: &Instruction, _node: &CFGNode) -> Self {
+        let mut exprs = self.0.clone();
+        if let Some(dest) = instr.get_dest() {
+            exprs.retain(|expr| !expr.uses(&dest));
+        }
+        exprs.extend(Expression::from_instr(instr));
+        Used(exprs)
+    }
+}
+
+impl DataFlowDisplay for Used {
+    fn generate_string(&self, cfg: &CFG) -> String {
+        ExprSet(Some(self.0.clone())).generate_string(cfg)
+    }
+}
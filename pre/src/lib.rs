@@ -0,0 +1,309 @@
+mod analyses;
+
+pub use analyses::{Anticipated, Available, ExprSet, Postponable, Used};
+
+use std::collections::{HashMap, HashSet};
+
+use bril_rs::{Instruction, ValueOps};
+use cfg::{CFGNode, CfgError, CFG};
+use df::{
+    available_exprs::Expression, live_vars::LiveVars, run_worklist, run_worklist_with, Analysis,
+};
+use itertools::Itertools;
+use petgraph::{
+    visit::{Dfs, Reversed, Walker},
+    Direction::Incoming,
+};
+use util::SafeAccess;
+
+type NodeSets = HashMap<CFGNode, HashSet<Expression>>;
+
+/// Where a computation is made: block index, instruction index and destination
+type Site = (usize, usize, String);
+
+/// Where an expression is computed before its uses, by block index
+#[derive(Clone, Copy)]
+enum Placement {
+    /// After the phis of the block
+    Start(usize),
+    /// Before the terminator of the block
+    End(usize),
+}
+
+/// Partial redundancy elimination by lazy code motion. Every edge into a join gets its own block,
+/// then each expression is computed as late as possible among the earliest points where it is
+/// anticipated. The placed value is held by the destination of the first computation when that is
+/// the only placement, or of the only computation, which moves to the placements. Other
+/// expressions are left alone, as a fresh temporary costs more copies than it saves.
+/// Expects a cfg not in ssa form, returns whether the cfg changed
+pub fn pre(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let mut work = cfg.clone();
+    let split = split_join_edges(&mut work);
+    let nodes: Vec<_> = work.graph.nodes().sorted().collect();
+    let reachable: HashSet<_> = Dfs::new(&work.graph, CFGNode::Block(0))
+        .iter(&work.graph)
+        .collect();
+    // Anticipation is only known on paths that end in a return
+    let exits = Dfs::new(Reversed(&work.graph), CFGNode::Return)
+        .iter(Reversed(&work.graph))
+        .collect::<HashSet<_>>();
+    if reachable.iter().any(|node| !exits.contains(node)) {
+        return Ok(false);
+    }
+
+    let anticipated = run_worklist::<Anticipated>(&work);
+    let ant_in = collect(&nodes, |node| anticipated.in_map[node].0.to_set());
+    // Expressions computed before any of their arguments change in a node
+    let used_locally = collect(&nodes, |node| {
+        Anticipated(ExprSet::empty())
+            .transfer(node, &work)
+            .0
+            .to_set()
+    });
+
+    let available = run_worklist_with(&work, Available::boundary(&work), |fact, node| {
+        Available(fact.0.union(&ant_in[node])).transfer(node, &work)
+    });
+    let earliest = collect(&nodes, |node| {
+        if !reachable.contains(node) {
+            return HashSet::new();
+        }
+        let avail_in = available.in_map[node].0.to_set();
+        ant_in[node].difference(&avail_in).cloned().collect()
+    });
+
+    let postponable = run_worklist_with(&work, Postponable::boundary(&work), |fact, node| {
+        Postponable(fact.0.union(&earliest[node])).transfer(node, &work)
+    });
+    let candidates = collect(&nodes, |node| {
+        let post_in = postponable.in_map[node].0.to_set();
+        earliest[node].union(&post_in).cloned().collect()
+    });
+    let latest = collect(&nodes, |node| {
+        candidates[node]
+            .iter()
+            .filter(|expr| {
+                used_locally[node].contains(expr)
+                    || work
+                        .graph
+                        .neighbors(*node)
+                        .any(|succ| !candidates[&succ].contains(expr))
+            })
+            .cloned()
+            .collect()
+    });
+
+    let used = run_worklist_with(&work, Used::boundary(&work), |fact, node| {
+        let Used(mut exprs) = fact.transfer(node, &work);
+        exprs.retain(|expr| !latest[node].contains(expr));
+        Used(exprs)
+    });
+
+    // Computations placed in a split block go to the end of its predecessor when that has no
+    // other successor. A split block that has to stay costs a jump on every pass, as much as the
+    // computation saved, so expressions placed there are left alone
+    let mut placements: HashMap<Expression, Vec<(Placement, CFGNode)>> = HashMap::new();
+    let mut skipped = HashSet::new();
+    for node in nodes.iter().filter(|node| reachable.contains(node)) {
+        let &CFGNode::Block(b) = node else {
+            continue;
+        };
+        let placement = match split.iter().find(|(_, block, _)| block == node) {
+            Some((from, _, _)) if work.graph.neighbors(*from).count() > 1 => None,
+            Some((CFGNode::Block(from), _, _)) => Some(Placement::End(*from)),
+            _ => Some(Placement::Start(b)),
+        };
+        for expr in latest[node].intersection(&used.out_map[node].0) {
+            match placement {
+                Some(placement) => placements
+                    .entry(expr.clone())
+                    .or_default()
+                    .push((placement, *node)),
+                None => {
+                    skipped.insert(expr.clone());
+                }
+            }
+        }
+    }
+
+    // Computations that read the value placed before them instead of computing it
+    let mut sites: HashMap<Expression, Vec<Site>> = HashMap::new();
+    for (b, block) in work.blocks.iter().enumerate() {
+        let node = CFGNode::Block(b);
+        if !reachable.contains(&node) {
+            continue;
+        }
+        let used_out = &used.out_map[&node].0;
+        let mut defined = HashSet::new();
+        for (i, instr) in block.instrs.iter().enumerate() {
+            let replace = Expression::from_instr(instr).filter(|expr| {
+                !skipped.contains(expr)
+                    && expr.args().iter().all(|arg| !defined.contains(arg))
+                    && (!latest[&node].contains(expr) || used_out.contains(expr))
+            });
+            if let (Some(expr), Some(dest)) = (replace, instr.get_dest()) {
+                sites.entry(expr).or_default().push((b, i, dest));
+            }
+            defined.extend(instr.get_dest());
+        }
+    }
+
+    let live = run_worklist::<LiveVars>(&work);
+    let def_counts = work
+        .blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter().flat_map(|instr| instr.get_dest()))
+        .chain(work.args.iter().map(|arg| arg.name.clone()))
+        .counts();
+    let mut starts: HashMap<usize, Vec<Instruction>> = HashMap::new();
+    let mut ends: HashMap<usize, Vec<Instruction>> = HashMap::new();
+    let mut rewrites: HashMap<(usize, usize), Option<Instruction>> = HashMap::new();
+    for (expr, placed) in placements
+        .into_iter()
+        .filter(|(expr, _)| !skipped.contains(expr))
+        .sorted_by_key(|(expr, _)| expr.to_string())
+    {
+        let expr_sites = sites.remove(&expr).unwrap_or_default();
+        let single_def = |dest: &String| def_counts[dest] == 1 && !expr.uses(dest);
+        let first_in = |b: usize| expr_sites.iter().position(|(site_b, _, _)| *site_b == b);
+        let temp = match (placed.as_slice(), expr_sites.as_slice()) {
+            // Placed only where it is first computed, so that computation already holds it
+            ([(Placement::Start(b), _)], _)
+                if first_in(*b).is_some_and(|first| single_def(&expr_sites[first].2)) =>
+            {
+                let (_, _, temp) = &expr_sites[first_in(*b).unwrap()];
+                for (b, i, dest) in expr_sites.iter().filter(|(_, _, dest)| dest != temp) {
+                    rewrites.insert((*b, *i), Some(copy(dest, temp, &expr)));
+                }
+                continue;
+            }
+            // The only computation reading it moves to where it is placed
+            (_, [(b, i, dest)])
+                if single_def(dest)
+                    && placed
+                        .iter()
+                        .all(|(_, node)| !live.in_map[node].contains(dest)) =>
+            {
+                rewrites.insert((*b, *i), None);
+                dest.clone()
+            }
+            // A fresh temporary would cost a copy at every computation it reaches
+            _ => continue,
+        };
+        for (placement, _) in placed {
+            let instr = expr.to_instr(&temp).unwrap();
+            match placement {
+                Placement::Start(b) => starts.entry(b).or_default().push(instr),
+                Placement::End(b) => ends.entry(b).or_default().push(instr),
+            }
+        }
+    }
+
+    let changed = !starts.is_empty() || !ends.is_empty() || !rewrites.is_empty();
+    for (b, block) in work.blocks.iter_mut().enumerate() {
+        let phis = block
+            .instrs
+            .iter()
+            .take_while(|instr| is_phi(instr))
+            .count();
+        let mut instrs: Vec<_> = block.instrs.drain(..phis).collect();
+        instrs.extend(starts.remove(&b).into_iter().flatten());
+        for (i, instr) in block.instrs.drain(..).enumerate() {
+            match rewrites.remove(&(b, i + phis)) {
+                Some(rewrite) => instrs.extend(rewrite),
+                None => instrs.push(instr),
+            }
+        }
+        let end = instrs.len() - 1;
+        instrs.splice(end..end, ends.remove(&b).into_iter().flatten());
+        block.instrs = instrs;
+    }
+
+    if !changed {
+        return Ok(false);
+    }
+    unsplit_empty(&mut work, &split)?;
+    *cfg = work;
+    Ok(true)
+}
+
+fn collect(nodes: &[CFGNode], f: impl Fn(&CFGNode) -> HashSet<Expression>) -> NodeSets {
+    nodes.iter().map(|node| (*node, f(node))).collect()
+}
+
+fn is_phi(instr: &Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::Value {
+            op: ValueOps::Phi,
+            ..
+        }
+    )
+}
+
+/// Inserts a block on every edge into a block with several predecessors, so computations can
+/// be placed on an edge without running on the other paths. Returns (from, split, to) triples
+fn split_join_edges(cfg: &mut CFG) -> Vec<(CFGNode, CFGNode, CFGNode)> {
+    let edges: Vec<_> = cfg
+        .graph
+        .all_edges()
+        .map(|(from, to, _)| (from, to))
+        .filter(|&(_, to)| {
+            matches!(to, CFGNode::Block(_))
+                && cfg.graph.neighbors_directed(to, Incoming).count() > 1
+        })
+        .sorted()
+        .collect();
+    edges
+        .into_iter()
+        .map(|(from, to)| (from, cfg.split_edge(from, to), to))
+        .collect()
+}
+
+/// Removes the split blocks that received no computation, pointing their edges back
+fn unsplit_empty(cfg: &mut CFG, split: &[(CFGNode, CFGNode, CFGNode)]) -> Result<(), CfgError> {
+    let mut removed = HashSet::new();
+    for &(from, node, to) in split {
+        if cfg.get_block(node).unwrap().instrs.len() > 1 {
+            continue;
+        }
+        let label = cfg.get_block(node).unwrap().label.clone();
+        let from_label = cfg.get_block(from).unwrap().label.clone();
+        let to_label = cfg.get_block(to).unwrap().label.clone();
+        if let Some(Instruction::Effect { labels, .. }) =
+            cfg.get_block_mut(from).unwrap().instrs.last_mut()
+        {
+            labels
+                .iter_mut()
+                .filter(|l| **l == label)
+                .for_each(|l| *l = to_label.clone());
+        }
+        for instr in &mut cfg.get_block_mut(to).unwrap().instrs {
+            if let Instruction::Value {
+                op: ValueOps::Phi,
+                labels,
+                ..
+            } = instr
+            {
+                labels
+                    .iter_mut()
+                    .filter(|l| **l == label)
+                    .for_each(|l| *l = from_label.clone());
+            }
+        }
+        removed.insert(node);
+    }
+    cfg.retain_blocks(|node, _| !removed.contains(&node))
+}
+
+fn copy(dest: &str, src: &str, expr: &Expression) -> Instruction {
+    Instruction::Value {
+        op: ValueOps::Id,
+        dest: dest.to_string(),
+        args: vec![src.to_string()],
+        funcs: Vec::new(),
+        labels: Vec::new(),
+        op_type: expr.kind().unwrap().clone(),
+        pos: None,
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet};
+
+use bril_rs::{ConstOps, EffectOps, Instruction, Literal, ValueOps};
+use cfg::{CFGNode, CfgError, Edge, CFG};
+use itertools::Itertools;
+use petgraph::Direction::Incoming;
+use simplify::remove_unreachable;
+use util::SafeAccess;
+
+/// Value of a variable over all executions found so far
+#[derive(Clone, Debug, PartialEq)]
+pub enum LatticeValue {
+    /// No definition has been executed yet
+    Unknown,
+    Constant(Literal),
+    /// Can hold different values
+    Overdefined,
+}
+
+impl LatticeValue {
+    pub fn meet(&self, other: &Self) -> Self {
+        match (self, other) {
+            (LatticeValue::Unknown, v) | (v, LatticeValue::Unknown) => v.clone(),
+            (LatticeValue::Constant(a), LatticeValue::Constant(b)) if a == b => self.clone(),
+            _ => LatticeValue::Overdefined,
+        }
+    }
+}
+
+pub struct SccpResult {
+    pub values: HashMap<String, LatticeValue>,
+    pub executable_blocks: HashSet<CFGNode>,
+    pub executable_edges: HashSet<(CFGNode, CFGNode)>,
+}
+
+/// Folds an operation whose arguments are all constants, None if it cannot be folded
+fn fold(op: ValueOps, args: &[Literal]) -> Option<Literal> {
+    use Literal::{Bool, Float, Int};
+    let result = match (op, args) {
+        (ValueOps::Id, [v]) => v.clone(),
+        (ValueOps::Add, [Int(a), Int(b)]) => Int(a.wrapping_add(*b)),
+        (ValueOps::Sub, [Int(a), Int(b)]) => Int(a.wrapping_sub(*b)),
+        (ValueOps::Mul, [Int(a), Int(b)]) => Int(a.wrapping_mul(*b)),
+        (ValueOps::Div, [Int(a), Int(b)]) if *b != 0 => Int(a.wrapping_div(*b)),
+        (ValueOps::Eq, [Int(a), Int(b)]) => Bool(a == b),
+        (ValueOps::Lt, [Int(a), Int(b)]) => Bool(a < b),
+        (ValueOps::Gt, [Int(a), Int(b)]) => Bool(a > b),
+        (ValueOps::Le, [Int(a), Int(b)]) => Bool(a <= b),
+        (ValueOps::Ge, [Int(a), Int(b)]) => Bool(a >= b),
+        (ValueOps::Not, [Bool(a)]) => Bool(!a),
+        (ValueOps::And, [Bool(a), Bool(b)]) => Bool(*a && *b),
+        (ValueOps::Or, [Bool(a), Bool(b)]) => Bool(*a || *b),
+        (ValueOps::Fadd, [Float(a), Float(b)]) => Float(a + b),
+        (ValueOps::Fsub, [Float(a), Float(b)]) => Float(a - b),
+        (ValueOps::Fmul, [Float(a), Float(b)]) => Float(a * b),
+        (ValueOps::Fdiv, [Float(a), Float(b)]) => Float(a / b),
+        (ValueOps::Feq, [Float(a), Float(b)]) => Bool(a == b),
+        (ValueOps::Flt, [Float(a), Float(b)]) => Bool(a < b),
+        (ValueOps::Fgt, [Float(a), Float(b)]) => Bool(a > b),
+        (ValueOps::Fle, [Float(a), Float(b)]) => Bool(a <= b),
+        (ValueOps::Fge, [Float(a), Float(b)]) => Bool(a >= b),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Wegman-Zadeck solver: an edge is visited once when it becomes executable, and an
+/// instruction is reevaluated only when one of its arguments is lowered
+struct Solver<'a> {
+    cfg: &'a CFG,
+    label_index: HashMap<&'a str, CFGNode>,
+    /// Variables with several definitions are not in ssa form and never refined
+    fixed: HashSet<String>,
+    /// Instructions reading each variable, as (block, index)
+    uses: HashMap<String, Vec<(usize, usize)>>,
+    /// Edges that were found executable but not visited yet
+    flow_worklist: Vec<(CFGNode, CFGNode)>,
+    /// Instructions reading a variable whose value was lowered
+    ssa_worklist: Vec<(usize, usize)>,
+    result: SccpResult,
+}
+
+impl<'a> Solver<'a> {
+    fn new(cfg: &'a CFG) -> Self {
+        let label_index = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block.label.as_str(), CFGNode::Block(i)))
+            .collect();
+        let defs = cfg
+            .blocks
+            .iter()
+            .flat_map(|block| block.instrs.iter().flat_map(|instr| instr.get_dest()))
+            .counts();
+        let fixed: HashSet<_> = defs
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(var, _)| var)
+            .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+            .collect();
+        let values = fixed
+            .iter()
+            .map(|var| (var.clone(), LatticeValue::Overdefined))
+            .collect();
+        let uses = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(b, block)| {
+                block.instrs.iter().enumerate().flat_map(move |(i, instr)| {
+                    instr
+                        .get_args()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(move |arg| (arg, (b, i)))
+                })
+            })
+            .into_group_map();
+        Solver {
+            cfg,
+            label_index,
+            fixed,
+            uses,
+            flow_worklist: Vec::new(),
+            ssa_worklist: Vec::new(),
+            result: SccpResult {
+                values,
+                executable_blocks: HashSet::new(),
+                executable_edges: HashSet::new(),
+            },
+        }
+    }
+
+    fn value(&self, var: &str) -> LatticeValue {
+        self.result
+            .values
+            .get(var)
+            .cloned()
+            .unwrap_or(LatticeValue::Unknown)
+    }
+
+    /// Lowers the value of var and queues its uses if it changed
+    fn lower(&mut self, var: &str, value: LatticeValue) {
+        if self.fixed.contains(var) {
+            return;
+        }
+        let old = self.value(var);
+        let new = old.meet(&value);
+        if new == old {
+            return;
+        }
+        self.result.values.insert(var.to_string(), new);
+        self.queue_uses(var);
+    }
+
+    fn queue_uses(&mut self, var: &str) {
+        if let Some(uses) = self.uses.get(var) {
+            self.ssa_worklist.extend(uses);
+        }
+    }
+
+    fn evaluate(&self, instr: &Instruction, node: CFGNode) -> LatticeValue {
+        match instr {
+            Instruction::Constant { value, .. } => LatticeValue::Constant(value.clone()),
+            Instruction::Value {
+                op: ValueOps::Phi,
+                args,
+                labels,
+                ..
+            } => args
+                .iter()
+                .zip(labels)
+                .filter(|(_, label)| {
+                    self.label_index
+                        .get(label.as_str())
+                        .is_some_and(|pred| self.result.executable_edges.contains(&(*pred, node)))
+                })
+                .fold(LatticeValue::Unknown, |acc, (arg, _)| {
+                    acc.meet(&self.value(arg))
+                }),
+            Instruction::Value { op, args, .. } => {
+                let values: Vec<_> = args.iter().map(|arg| self.value(arg)).collect();
+                if values.contains(&LatticeValue::Overdefined) {
+                    return LatticeValue::Overdefined;
+                }
+                let literals: Option<Vec<_>> = values
+                    .into_iter()
+                    .map(|value| match value {
+                        LatticeValue::Constant(literal) => Some(literal),
+                        _ => None,
+                    })
+                    .collect();
+                match literals {
+                    Some(literals) => fold(*op, &literals)
+                        .map_or(LatticeValue::Overdefined, LatticeValue::Constant),
+                    None => LatticeValue::Unknown,
+                }
+            }
+            Instruction::Effect { .. } => LatticeValue::Unknown,
+        }
+    }
+
+    /// Evaluates one instruction of an executable block, queueing the outgoing edges
+    /// of the block when the instruction is a branch
+    fn visit_instr(&mut self, block: usize, index: usize) {
+        let node = CFGNode::Block(block);
+        let instr = &self.cfg.blocks[block].instrs[index];
+        if let Some(dest) = instr.get_dest() {
+            let value = self.evaluate(instr, node);
+            self.lower(&dest, value);
+        } else if let Instruction::Effect {
+            op: EffectOps::Branch,
+            ..
+        } = instr
+        {
+            self.visit_edges(block);
+        }
+    }
+
+    /// Queues the outgoing edges of a block that its branch condition allows
+    fn visit_edges(&mut self, block: usize) {
+        let node = CFGNode::Block(block);
+        let condition = self.branch_condition(block);
+        for (_, succ, edge) in self.cfg.graph.edges(node) {
+            let taken = match (edge, &condition) {
+                (Edge::Bool(b), Some(LatticeValue::Constant(Literal::Bool(c)))) => b == c,
+                (Edge::Bool(_), Some(LatticeValue::Unknown)) => false,
+                _ => true,
+            };
+            if taken && !self.result.executable_edges.contains(&(node, succ)) {
+                self.flow_worklist.push((node, succ));
+            }
+        }
+    }
+
+    /// Marks an edge executable. The first edge into a block evaluates all of it,
+    /// later ones only reevaluate its phis
+    fn visit_edge(&mut self, from: CFGNode, to: CFGNode) {
+        if !self.result.executable_edges.insert((from, to)) {
+            return;
+        }
+        let first = self.result.executable_blocks.insert(to);
+        let CFGNode::Block(block) = to else {
+            return;
+        };
+        for (index, instr) in self.cfg.blocks[block].instrs.iter().enumerate() {
+            let is_phi = matches!(
+                instr,
+                Instruction::Value {
+                    op: ValueOps::Phi,
+                    ..
+                }
+            );
+            if first || is_phi {
+                self.visit_instr(block, index);
+            }
+        }
+        if first {
+            self.visit_edges(block);
+        }
+    }
+
+    fn branch_condition(&self, block: usize) -> Option<LatticeValue> {
+        match self.cfg.blocks[block].instrs.last() {
+            Some(Instruction::Effect {
+                op: EffectOps::Branch,
+                args,
+                ..
+            }) => Some(self.value(&args[0])),
+            _ => None,
+        }
+    }
+
+    fn run(&mut self) {
+        loop {
+            if let Some((from, to)) = self.flow_worklist.pop() {
+                self.visit_edge(from, to);
+            } else if let Some((block, index)) = self.ssa_worklist.pop() {
+                if self
+                    .result
+                    .executable_blocks
+                    .contains(&CFGNode::Block(block))
+                {
+                    self.visit_instr(block, index);
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn solve(mut self) -> SccpResult {
+        self.result.executable_blocks.insert(CFGNode::Block(0));
+        for index in 0..self.cfg.blocks[0].instrs.len() {
+            self.visit_instr(0, index);
+        }
+        self.visit_edges(0);
+        loop {
+            self.run();
+            // A condition that is still unknown only reads undefined values, so both
+            // edges stay live rather than dropping code the program could still reach
+            let unresolved: Vec<_> = self
+                .result
+                .executable_blocks
+                .iter()
+                .filter_map(|node| match node {
+                    CFGNode::Block(i) => match self.cfg.blocks[*i].instrs.last() {
+                        Some(Instruction::Effect {
+                            op: EffectOps::Branch,
+                            args,
+                            ..
+                        }) if self.value(&args[0]) == LatticeValue::Unknown => {
+                            Some(args[0].clone())
+                        }
+                        _ => None,
+                    },
+                    CFGNode::Return => None,
+                })
+                .collect();
+            if unresolved.is_empty() {
+                return self.result;
+            }
+            for var in unresolved {
+                self.result
+                    .values
+                    .insert(var.clone(), LatticeValue::Overdefined);
+                self.queue_uses(&var);
+            }
+        }
+    }
+}
+
+/// Runs sparse conditional constant propagation on a cfg in ssa form
+pub fn analyze(cfg: &CFG) -> SccpResult {
+    Solver::new(cfg).solve()
+}
+
+/// Replaces definitions of constant variables with constants, folds branches on constant
+/// conditions into jumps and deletes the blocks that can no longer run.
+/// Expects a cfg in ssa form, returns whether the cfg changed
+pub fn sccp(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let result = analyze(cfg);
+    let mut dirty = false;
+
+    for block in &mut cfg.blocks {
+        for instr in &mut block.instrs {
+            match instr {
+                Instruction::Value {
+                    op,
+                    dest,
+                    op_type,
+                    pos,
+                    ..
+                } if *op != ValueOps::Call => {
+                    if let Some(LatticeValue::Constant(value)) = result.values.get(dest) {
+                        *instr = Instruction::Constant {
+                            dest: dest.clone(),
+                            op: ConstOps::Const,
+                            const_type: op_type.clone(),
+                            value: value.clone(),
+                            pos: pos.clone(),
+                        };
+                        dirty = true;
+                    }
+                }
+                Instruction::Effect {
+                    op: op @ EffectOps::Branch,
+                    args,
+                    labels,
+                    ..
+                } => {
+                    if let Some(LatticeValue::Constant(Literal::Bool(c))) =
+                        result.values.get(&args[0])
+                    {
+                        let target = if *c {
+                            labels[0].clone()
+                        } else {
+                            labels[1].clone()
+                        };
+                        *op = EffectOps::Jump;
+                        *args = Vec::new();
+                        *labels = vec![target];
+                        dirty = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if !dirty {
+        return Ok(false);
+    }
+
+    cfg.rebuild_graph()?;
+    remove_unreachable(cfg)?;
+    prune_phis(cfg);
+    Ok(true)
+}
+
+/// Drops phi arguments coming from blocks that no longer jump to the phi's block
+fn prune_phis(cfg: &mut CFG) {
+    for i in 0..cfg.blocks.len() {
+        let preds: HashSet<_> = cfg
+            .graph
+            .neighbors_directed(CFGNode::Block(i), Incoming)
+            .filter_map(|pred| cfg.get_block(pred))
+            .map(|block| block.label.clone())
+            .collect();
+        for instr in &mut cfg.blocks[i].instrs {
+            if let Instruction::Value {
+                op: ValueOps::Phi,
+                args,
+                labels,
+                ..
+            } = instr
+            {
+                let (kept_args, kept_labels) = args
+                    .iter()
+                    .cloned()
+                    .zip(labels.iter().cloned())
+                    .filter(|(_, label)| preds.contains(label))
+                    .unzip();
+                *args = kept_args;
+                *labels = kept_labels;
+            }
+        }
+    }
+}
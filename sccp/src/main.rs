@@ -0,0 +1,28 @@
+use std::{io, process};
+
+use bril_rs::{load_program_from_read, output_program};
+use cfg::generate_cfg;
+use sccp::sccp;
+use ssa::{convert_from_ssa, convert_to_ssa, SSAMode};
+
+fn main() -> io::Result<()> {
+    let mut program = load_program_from_read(io::stdin());
+
+    for function in &mut program.functions {
+        let cfg = generate_cfg(function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        let mut ssa_cfg = convert_to_ssa(&cfg, SSAMode::default());
+        let changed = sccp(&mut ssa_cfg).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        if changed {
+            *function = convert_from_ssa(&ssa_cfg).to_function();
+        }
+    }
+
+    output_program(&program);
+    Ok(())
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+
+use bbb::Block;
+use bril_rs::{EffectOps, Instruction, ValueOps};
+use cfg::{CFGNode, CfgError, CFG};
+use petgraph::{visit::Dfs, Direction::Incoming};
+
+/// Repeats every simplification until none applies, returns whether the cfg changed
+pub fn simplify_cfg(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let mut dirty = false;
+    loop {
+        let mut changed = remove_unreachable(cfg)?;
+        changed |= fold_branches(cfg)?;
+        changed |= thread_jumps(cfg)?;
+        changed |= merge_blocks(cfg)?;
+        if !changed {
+            return Ok(dirty);
+        }
+        dirty = true;
+    }
+}
+
+fn is_phi(instr: &Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::Value {
+            op: ValueOps::Phi,
+            ..
+        }
+    )
+}
+
+fn jump(label: String) -> Instruction {
+    Instruction::Effect {
+        op: EffectOps::Jump,
+        labels: vec![label],
+        args: Vec::new(),
+        funcs: Vec::new(),
+        pos: None,
+    }
+}
+
+/// Returns the target of a block that only jumps somewhere else
+fn jump_only_target(block: &Block) -> Option<&String> {
+    match block.instrs.as_slice() {
+        [Instruction::Effect {
+            op: EffectOps::Jump,
+            labels,
+            ..
+        }] if labels[0] != block.label => labels.first(),
+        _ => None,
+    }
+}
+
+/// Deletes blocks that cannot be reached from the entry block
+pub fn remove_unreachable(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let mut reachable = HashSet::new();
+    let mut dfs = Dfs::new(&cfg.graph, CFGNode::Block(0));
+    while let Some(node) = dfs.next(&cfg.graph) {
+        reachable.insert(node);
+    }
+    let removed_labels: HashSet<_> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !reachable.contains(&CFGNode::Block(*i)))
+        .map(|(_, block)| block.label.clone())
+        .collect();
+    if removed_labels.is_empty() {
+        return Ok(false);
+    }
+
+    // Phis in the remaining blocks can no longer be reached from removed predecessors
+    for instr in cfg.blocks.iter_mut().flat_map(|b| b.instrs.iter_mut()) {
+        if let Instruction::Value {
+            op: ValueOps::Phi,
+            args,
+            labels,
+            ..
+        } = instr
+        {
+            let (kept_args, kept_labels) = args
+                .iter()
+                .cloned()
+                .zip(labels.iter().cloned())
+                .filter(|(_, label)| !removed_labels.contains(label))
+                .unzip();
+            *args = kept_args;
+            *labels = kept_labels;
+        }
+    }
+    cfg.retain_blocks(|node, _| reachable.contains(&node))?;
+    Ok(true)
+}
+
+/// Replaces branches whose targets are identical with jumps
+pub fn fold_branches(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let mut dirty = false;
+    for block in &mut cfg.blocks {
+        if let Some(Instruction::Effect {
+            op: EffectOps::Branch,
+            labels,
+            ..
+        }) = block.instrs.last()
+        {
+            if labels[0] == labels[1] {
+                let target = labels[0].clone();
+                *block.instrs.last_mut().unwrap() = jump(target);
+                dirty = true;
+            }
+        }
+    }
+    if dirty {
+        cfg.rebuild_graph()?;
+    }
+    Ok(dirty)
+}
+
+/// Retargets jumps and branches to blocks that only hold a jump,
+/// unless the final target has phis that would need new predecessors
+pub fn thread_jumps(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let label_index: HashMap<_, _> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.label.clone(), i))
+        .collect();
+    let mut dirty = false;
+    for i in 1..cfg.blocks.len() {
+        let Some(target) = jump_only_target(&cfg.blocks[i]).cloned() else {
+            continue;
+        };
+        if cfg.blocks[label_index[&target]].instrs.iter().any(is_phi) {
+            continue;
+        }
+        let label = cfg.blocks[i].label.clone();
+        for block in &mut cfg.blocks {
+            if let Some(Instruction::Effect {
+                op: EffectOps::Jump | EffectOps::Branch,
+                labels,
+                ..
+            }) = block.instrs.last_mut()
+            {
+                for l in labels.iter_mut().filter(|l| **l == label) {
+                    *l = target.clone();
+                    dirty = true;
+                }
+            }
+        }
+    }
+    if dirty {
+        cfg.rebuild_graph()?;
+    }
+    Ok(dirty)
+}
+
+/// Merges every block into its predecessor when that predecessor jumps to it
+/// and is its only predecessor
+pub fn merge_blocks(cfg: &mut CFG) -> Result<bool, CfgError> {
+    let label_index: HashMap<_, _> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.label.clone(), i))
+        .collect();
+    let pred_count: Vec<_> = (0..cfg.blocks.len())
+        .map(|i| {
+            cfg.graph
+                .neighbors_directed(CFGNode::Block(i), Incoming)
+                .count()
+        })
+        .collect();
+    let mut removed = HashSet::new();
+
+    for a in 0..cfg.blocks.len() {
+        if removed.contains(&a) {
+            continue;
+        }
+        while let Some(Instruction::Effect {
+            op: EffectOps::Jump,
+            labels,
+            ..
+        }) = cfg.blocks[a].instrs.last()
+        {
+            let b = label_index[&labels[0]];
+            let a_label = cfg.blocks[a].label.clone();
+            if b == a || b == 0 || pred_count[b] != 1 {
+                break;
+            }
+            let absorbed = std::mem::take(&mut cfg.blocks[b].instrs);
+            let b_label = cfg.blocks[b].label.clone();
+            removed.insert(b);
+
+            let a_instrs = &mut cfg.blocks[a].instrs;
+            a_instrs.pop();
+            a_instrs.extend(
+                absorbed
+                    .into_iter()
+                    .filter_map(|instr| phi_to_id(instr, &a_label)),
+            );
+
+            // Successors now see the merged block as their predecessor
+            for instr in cfg.blocks.iter_mut().flat_map(|b| b.instrs.iter_mut()) {
+                if let Instruction::Value {
+                    op: ValueOps::Phi,
+                    labels,
+                    ..
+                } = instr
+                {
+                    for l in labels.iter_mut().filter(|l| **l == b_label) {
+                        *l = a_label.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    if removed.is_empty() {
+        return Ok(false);
+    }
+    cfg.retain_blocks(|node, _| !matches!(node, CFGNode::Block(i) if removed.contains(&i)))?;
+    Ok(true)
+}
+
+/// Turns a phi of a block with a single predecessor into a copy of that predecessor's argument.
+/// A phi reading an undefined value is dropped, leaving its destination unassigned as ssa
+/// destruction does
+fn phi_to_id(instr: Instruction, pred_label: &str) -> Option<Instruction> {
+    match instr {
+        Instruction::Value {
+            op: ValueOps::Phi,
+            args,
+            labels,
+            dest,
+            funcs,
+            pos,
+            op_type,
+        } => {
+            let index = labels.iter().position(|l| l == pred_label).unwrap_or(0);
+            let arg = args.into_iter().nth(index)?;
+            (arg != "undefined").then(|| Instruction::Value {
+                op: ValueOps::Id,
+                args: vec![arg],
+                labels: Vec::new(),
+                dest,
+                funcs,
+                pos,
+                op_type,
+            })
+        }
+        _ => Some(instr),
+    }
+}
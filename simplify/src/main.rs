@@ -0,0 +1,24 @@
+use std::{io, process};
+
+use bril_rs::{load_program_from_read, output_program};
+use cfg::generate_cfg;
+use simplify::simplify_cfg;
+
+fn main() -> io::Result<()> {
+    let mut program = load_program_from_read(io::stdin());
+
+    for function in &mut program.functions {
+        let mut cfg = generate_cfg(function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        simplify_cfg(&mut cfg).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        *function = cfg.to_function();
+    }
+
+    output_program(&program);
+    Ok(())
+}
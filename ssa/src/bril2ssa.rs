@@ -1,13 +1,16 @@
 use bril_rs::{Instruction, Type, ValueOps};
 use cfg::{CFGNode, DataFlowHelpers, CFG};
+use df::{live_vars::LiveVars, run_worklist};
+use dom::{DomResult, DomTree};
 use itertools::Itertools;
-use petgraph::prelude::DiGraphMap;
 use std::{
     cmp::Reverse,
     collections::{HashMap, HashSet},
 };
 use util::SafeAccess;
 
+use crate::SSAMode;
+
 pub struct SSAConverter {
     pub cfg: CFG,
     stack: VariableStack,
@@ -24,76 +27,72 @@ impl SSAConverter {
         }
     }
 
-    pub fn make_phi_nodes(
-        &mut self,
-        dominance_frontier: &HashMap<CFGNode, HashSet<CFGNode>>,
-    ) -> PhiResult {
-        let mut result: HashMap<CFGNode, Vec<Phi>> = HashMap::new();
-        let mut seen: HashMap<&String, HashSet<cfg::CFGNode>> = HashMap::new();
-        for (variable, def_nodes) in self
-            .cfg
-            .get_defs()
-            .clone()
-            .iter()
-            .sorted_by_key(|x| Reverse(x.0))
-        {
-            let mut def_stack = def_nodes.into_iter().collect_vec();
-            let mut def_type_opt = None;
-            let mut i = 0;
-            while let Some(def_node) = def_stack.get(i) {
-                i += 1;
-                let def_block = self.cfg.get_block(**def_node);
-                if def_block.is_none() {
-                    continue;
-                }
-                let def_instrs = &def_block.unwrap().instrs;
-                let def_type = def_type_opt.get_or_insert_with(|| {
-                    def_instrs
-                        .iter()
-                        .find(|instr| instr.get_dest().map_or(false, |d| &d == variable))
-                        .and_then(|instr| instr.get_type())
-                        .unwrap()
-                        .clone()
-                });
-                for node in dominance_frontier
-                    .get(&def_node)
-                    .expect("dominance frontier exists for all nodes")
-                {
-                    // add phi node to block
-                    if seen
-                        .get(variable)
-                        .map_or(true, |added| !added.contains(node))
-                    {
-                        seen.entry(variable)
-                            .or_insert_with(|| HashSet::new())
-                            .insert(*node);
-                        let labels = self
-                            .cfg
-                            .graph
-                            .neighbors_directed(*node, petgraph::Direction::Incoming)
-                            .sorted()
-                            .map(|pred| self.cfg.get_block(pred).unwrap().label.to_string())
-                            .collect();
-                        let phi = Phi::new(variable.to_owned(), def_type.clone(), labels);
-                        result.entry(*node).or_default().push(phi);
-                    }
+    pub fn make_phi_nodes(&mut self, dom: &DomResult<CFGNode>, mode: SSAMode) -> PhiResult {
+        let globals = match mode {
+            SSAMode::SemiPruned => Some(self.globals()),
+            _ => None,
+        };
+        let live = match mode {
+            SSAMode::Pruned => Some(run_worklist::<LiveVars>(&self.cfg)),
+            _ => None,
+        };
+        let needs_phi = |variable: &String, node: CFGNode| {
+            globals.as_ref().is_none_or(|g| g.contains(variable))
+                && live.as_ref().is_none_or(|live| {
+                    live.in_map
+                        .get(&node)
+                        .is_some_and(|vars| vars.contains(variable))
+                })
+        };
+
+        let mut result: PhiResult = HashMap::new();
+        for (variable, def_nodes) in self.cfg.get_defs().iter().sorted_by_key(|x| Reverse(x.0)) {
+            let def_type = def_nodes
+                .iter()
+                .flat_map(|&node| self.cfg.get_block(node))
+                .flat_map(|block| &block.instrs)
+                .find(|instr| instr.get_dest().as_ref() == Some(variable))
+                .and_then(|instr| instr.get_type())
+                .unwrap()
+                .clone();
+            for node in dom
+                .iterated_frontier(def_nodes.iter().copied())
+                .into_iter()
+                .sorted()
+                .filter(|&node| needs_phi(variable, node))
+            {
+                let labels = self
+                    .cfg
+                    .graph
+                    .neighbors_directed(node, petgraph::Direction::Incoming)
+                    .sorted()
+                    .map(|pred| self.cfg.get_block(pred).unwrap().label.to_string())
+                    .collect();
+                let phi = Phi::new(variable.to_owned(), def_type.clone(), labels);
+                result.entry(node).or_default().push(phi);
+            }
+        }
+        result
+    }
 
-                    // add block to defs
-                    if !def_stack.contains(&node) {
-                        def_stack.push(node);
+    /// Returns the variables read in some block before being assigned in it
+    fn globals(&self) -> HashSet<String> {
+        let mut globals = HashSet::new();
+        for block in &self.cfg.blocks {
+            let mut assigned = HashSet::new();
+            for instr in &block.instrs {
+                for arg in instr.get_args().unwrap_or_default() {
+                    if !assigned.contains(&arg) {
+                        globals.insert(arg);
                     }
                 }
+                assigned.extend(instr.get_dest());
             }
         }
-        result
+        globals
     }
 
-    pub fn rename(
-        &mut self,
-        node: CFGNode,
-        phi_nodes: &mut PhiResult,
-        dominator_tree: &DiGraphMap<CFGNode, ()>,
-    ) {
+    pub fn rename(&mut self, node: CFGNode, phi_nodes: &mut PhiResult, tree: &DomTree<CFGNode>) {
         if matches!(node, CFGNode::Return) {
             return;
         }
@@ -115,7 +114,15 @@ impl SSAConverter {
             }
         }
         for instr in &mut block.borrow_mut().instrs {
-            if let Some(old_args) = instr.get_args() {
+            // Arguments of phis already in the program are renamed from their predecessors
+            let is_phi = matches!(
+                instr,
+                Instruction::Value {
+                    op: ValueOps::Phi,
+                    ..
+                }
+            );
+            if let Some(old_args) = instr.get_args().filter(|_| !is_phi) {
                 let new_args = old_args
                     .iter()
                     .map(|arg| self.stack.get_last(arg).unwrap_or(arg.to_string()))
@@ -128,6 +135,25 @@ impl SSAConverter {
             }
         }
         for succ in self.cfg.graph.neighbors(node) {
+            if let Some(succ_block) = blocks_mut.get(&succ) {
+                for instr in &mut succ_block.borrow_mut().instrs {
+                    if let Instruction::Value {
+                        op: ValueOps::Phi,
+                        args,
+                        labels,
+                        ..
+                    } = instr
+                    {
+                        for (arg, label) in args.iter_mut().zip(labels.iter()) {
+                            if label == block_label {
+                                if let Some(name) = self.stack.get_last(arg) {
+                                    *arg = name;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
             if let Some(phis) = phi_nodes.get_mut(&succ) {
                 for phi in phis {
                     if let Some(name) = self.stack.get_last(&phi.canonical) {
@@ -139,8 +165,8 @@ impl SSAConverter {
             }
         }
 
-        for next in dominator_tree.neighbors(node) {
-            self.rename(next, phi_nodes, &dominator_tree);
+        for next in tree.children(node) {
+            self.rename(next, phi_nodes, tree);
         }
 
         for (variable, n) in &to_pop {
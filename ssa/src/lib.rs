@@ -1,4 +1,5 @@
 use bril2ssa::SSAConverter;
+use bril_rs::{Instruction, ValueOps};
 use cfg::{CFGNode, CFG};
 use dom::DominatorUtil;
 use ssa2bril::remove_phi_nodes;
@@ -6,17 +7,49 @@ use ssa2bril::remove_phi_nodes;
 mod bril2ssa;
 mod ssa2bril;
 
-pub fn convert_to_ssa(source: &CFG) -> CFG {
+/// Which variables get a phi at the iterated dominance frontier of their definitions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SSAMode {
+    /// Every variable at every frontier
+    Minimal,
+    /// Only variables read in some block before being assigned in it, since no other variable
+    /// is live across blocks
+    SemiPruned,
+    /// Only where the variable is live on entry to the block
+    #[default]
+    Pruned,
+}
+
+pub fn convert_to_ssa(source: &CFG, mode: SSAMode) -> CFG {
     let dom = source.find_dominators();
     let mut converter = SSAConverter::new(source.clone());
-    let mut phi_nodes = converter.make_phi_nodes(&dom.dominance_frontier);
-    converter.rename(CFGNode::Block(0), &mut phi_nodes, &dom.dominator_tree);
+    let mut phi_nodes = converter.make_phi_nodes(&dom, mode);
+    converter.rename(CFGNode::Block(0), &mut phi_nodes, &dom.tree);
     converter.insert_phi_nodes(&phi_nodes);
     converter.cfg
 }
 
+/// Returns the number of phi instructions in cfg
+pub fn count_phis(cfg: &CFG) -> usize {
+    cfg.blocks
+        .iter()
+        .flat_map(|block| &block.instrs)
+        .filter(|instr| {
+            matches!(
+                instr,
+                Instruction::Value {
+                    op: ValueOps::Phi,
+                    ..
+                }
+            )
+        })
+        .count()
+}
+
 pub fn convert_from_ssa(source: &CFG) -> CFG {
     let mut cfg = source.clone();
+    // Copies for a phi can only be placed in a predecessor that has no other successors
+    cfg.split_critical_edges();
     remove_phi_nodes(&mut cfg);
     cfg
 }
@@ -1,28 +1,66 @@
-use std::{env, io};
+use std::{env, io, process};
 
-use bbb::ToCode;
 use bril_rs::{load_program, output_program};
 use cfg::generate_cfg;
-use ssa::{convert_from_ssa, convert_to_ssa};
+use ssa::{convert_from_ssa, convert_to_ssa, count_phis, SSAMode};
+
+/// Returns the phi placement asked for with --mode, exiting on unknown modes
+fn ssa_mode(args: &[String]) -> SSAMode {
+    let Some(i) = args.iter().position(|arg| arg == "--mode") else {
+        return SSAMode::default();
+    };
+    match args.get(i + 1).map(String::as_str) {
+        Some("minimal") => SSAMode::Minimal,
+        Some("semi-pruned") => SSAMode::SemiPruned,
+        Some("pruned") => SSAMode::Pruned,
+        mode => {
+            eprintln!("Unknown mode: {}", mode.unwrap_or_default());
+            process::exit(1);
+        }
+    }
+}
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
-    // Valid values: ["into", "full"], defaults to "full" if empty
-    let mode = args.get(1).map(|a| a.as_str()).unwrap_or("full");
+    // Valid values: ["into", "from", "full"], defaults to "full" if empty
+    let conversion = args
+        .get(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(|a| a.as_str())
+        .unwrap_or("full");
+    let mode = ssa_mode(&args);
+    // Phi counts go to stderr so the program can still be piped
+    let count = args.iter().any(|arg| arg == "--count-phis");
 
     let mut program = load_program();
+    let mut total = 0;
     for function in &mut program.functions {
-        let cfg = generate_cfg(&function);
-        let ssa_cfg = convert_to_ssa(&cfg);
-        if mode == "into" {
-            function.instrs = ssa_cfg.blocks.to_code();
+        let cfg = generate_cfg(function).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        });
+        if conversion == "from" {
+            *function = convert_from_ssa(&cfg).to_function();
             continue;
         }
-        if mode == "full" {
+        let ssa_cfg = convert_to_ssa(&cfg, mode);
+        if count {
+            let phis = count_phis(&ssa_cfg);
+            eprintln!("@{}: {} phis", function.name, phis);
+            total += phis;
+        }
+        if conversion == "into" {
+            *function = ssa_cfg.to_function();
+            continue;
+        }
+        if conversion == "full" {
             let out_cfg = convert_from_ssa(&ssa_cfg);
-            function.instrs = out_cfg.blocks.to_code();
+            *function = out_cfg.to_function();
         }
     }
+    if count {
+        eprintln!("total: {} phis", total);
+    }
     output_program(&program);
     Ok(())
 }
@@ -1,80 +1,224 @@
+use std::collections::{HashMap, HashSet};
+
 use bril_rs::{Instruction, Type, ValueOps};
 use cfg::{CFGNode, CFG};
-use petgraph::Direction::Incoming;
+use df::{definite_assignment::DefinitelyAssigned, run_worklist};
+use itertools::Itertools;
+use petgraph::Direction::{Incoming, Outgoing};
+use util::SafeAccess;
+
+/// A copy of src into dest that is part of a parallel copy
+struct PhiCopy {
+    dest: String,
+    src: String,
+    op_type: Type,
+}
+
+fn id(dest: String, src: String, op_type: Type) -> Instruction {
+    Instruction::Value {
+        dest,
+        op_type,
+        op: ValueOps::Id,
+        args: vec![src],
+        funcs: Vec::new(),
+        labels: Vec::new(),
+        pos: None,
+    }
+}
 
-trait DefaultValue {
-    fn generate_default(&self, dest: String) -> Instruction;
+/// Returns a variable starting with base that is not in used, and marks it used
+fn fresh_var(used: &mut HashSet<String>, base: &str) -> String {
+    let var = (0..)
+        .map(|i| format!("{}.tmp.{}", base, i))
+        .find(|var| !used.contains(var))
+        .unwrap();
+    used.insert(var.clone());
+    var
 }
 
-impl DefaultValue for Type {
-    fn generate_default(&self, dest: String) -> Instruction {
-        let value = match self {
-            Type::Int => bril_rs::Literal::Int(i64::default()),
-            Type::Bool => bril_rs::Literal::Bool(bool::default()),
-            Type::Float => bril_rs::Literal::Float(f64::default()),
-            Type::Char => bril_rs::Literal::Char(char::default()),
-            Type::Pointer(_) => bril_rs::Literal::Int(i64::default()),
-        };
-        Instruction::Constant {
-            dest,
-            const_type: self.to_owned(),
-            op: bril_rs::ConstOps::Const,
-            pos: None,
-            value,
+/// Orders a parallel copy so every source is read before it is overwritten,
+/// saving a variable in a temporary only when the remaining copies form cycles
+fn sequentialize(mut copies: Vec<PhiCopy>, used: &mut HashSet<String>) -> Vec<Instruction> {
+    let mut instrs = Vec::new();
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|copy| copies.iter().all(|other| other.src != copy.dest));
+        match ready {
+            Some(i) => {
+                let PhiCopy { dest, src, op_type } = copies.remove(i);
+                instrs.push(id(dest, src, op_type));
+            }
+            None => {
+                let dest = copies[0].dest.clone();
+                let temp = fresh_var(used, &dest);
+                instrs.push(id(temp.clone(), dest.clone(), copies[0].op_type.clone()));
+                for copy in copies.iter_mut().filter(|copy| copy.src == dest) {
+                    copy.src = temp.clone();
+                }
+            }
         }
     }
+    instrs
 }
 
-pub fn remove_phi_nodes(cfg: &mut CFG) {
-    let n = cfg.blocks.len();
-    let blocks_mut = CFG::split_blocks_mut(&mut cfg.blocks);
-    for i in 1..n {
-        let node = CFGNode::Block(i);
-        let block = blocks_mut.get(&node).unwrap().borrow().clone();
-        block.instrs.iter().for_each(|instr| {
-            if let Instruction::Value {
+/// Returns the destinations of phis whose value reaches an instruction other than a phi,
+/// so that phis only feeding each other need no copies
+fn live_phi_dests(cfg: &CFG) -> HashSet<String> {
+    let mut phi_args = HashMap::new();
+    let mut live = HashSet::new();
+    for instr in cfg.blocks.iter().flat_map(|block| &block.instrs) {
+        match instr {
+            Instruction::Value {
                 op: ValueOps::Phi,
-                args,
-                labels,
                 dest,
-                op_type,
+                args,
                 ..
-            } = instr
-            {
-                for pred in cfg.graph.neighbors_directed(node, Incoming) {
-                    let mut pred_block = blocks_mut.get(&pred).unwrap().borrow_mut();
-                    let pred_label = pred_block.label.clone();
-                    let arg_index = labels
-                        .iter()
-                        .position(|label| label == &pred_label)
-                        .unwrap();
-                    let pred_instrs = &mut pred_block.instrs;
-                    let arg = args[arg_index].clone();
-                    if arg == "undefined" {
-                        pred_instrs.insert(
-                            pred_instrs.len() - 1,
-                            op_type.generate_default(dest.to_string()),
-                        );
-                        continue;
+            } => {
+                phi_args.insert(dest.clone(), args.clone());
+            }
+            _ => live.extend(instr.get_args().unwrap_or_default()),
+        }
+    }
+    let mut worklist: Vec<_> = live.iter().cloned().collect();
+    while let Some(var) = worklist.pop() {
+        for arg in phi_args.get(&var).into_iter().flatten() {
+            if live.insert(arg.clone()) {
+                worklist.push(arg.clone());
+            }
+        }
+    }
+    live
+}
+
+/// Writes the source of a copy straight into its destination when the source is defined in
+/// the predecessor, read by nothing but this parallel copy, and the destination is not read after
+/// it there. Other copies of the source then read the destination
+fn coalesce(copies: &mut Vec<PhiCopy>, instrs: &mut [Instruction], uses: &HashMap<String, usize>) {
+    let mut i = 0;
+    while i < copies.len() {
+        let PhiCopy { dest, src, .. } = &copies[i];
+        let def = instrs.iter().rposition(|instr| {
+            instr.get_dest().as_ref() == Some(src)
+                && !matches!(
+                    instr,
+                    Instruction::Value {
+                        op: ValueOps::Phi,
+                        ..
                     }
-                    pred_instrs.insert(
-                        pred_instrs.len() - 1,
-                        Instruction::Value {
-                            dest: dest.to_string(),
-                            op_type: op_type.clone(),
-                            op: ValueOps::Id,
-                            args: vec![arg],
-                            funcs: Vec::new(),
-                            labels: Vec::new(),
-                            pos: None,
-                        },
-                    )
+                )
+        });
+        let reads = copies.iter().filter(|copy| copy.src == *src).count();
+        let coalescable = def.is_some_and(|def| {
+            uses.get(src) == Some(&reads)
+                && copies.iter().all(|other| other.src != *dest)
+                && instrs[def + 1..].iter().all(|instr| {
+                    !instr.get_args().unwrap_or_default().contains(dest)
+                        && instr.get_dest().as_ref() != Some(dest)
+                })
+        });
+        match (def, coalescable) {
+            (Some(def), true) => {
+                let copy = copies.remove(i);
+                for other in copies.iter_mut().filter(|other| other.src == copy.src) {
+                    other.src = copy.dest.clone();
                 }
+                instrs[def].set_dest(copy.dest);
             }
-        });
+            _ => i += 1,
+        }
+    }
+}
+
+/// Replaces the phis of every block with one parallel copy per incoming edge.
+/// Critical edges must already be split, so the copies of an edge go either at the end of a
+/// predecessor with a single successor or at the start of a block with a single predecessor.
+/// Dead phis are dropped, and an argument that may be unassigned at the end of its predecessor
+/// leaves the phi's destination unassigned along its edge
+pub fn remove_phi_nodes(cfg: &mut CFG) {
+    let assigned = run_worklist::<DefinitelyAssigned>(cfg);
+    let live = live_phi_dests(cfg);
+    let mut uses = HashMap::new();
+    for arg in cfg
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instrs)
+        .flat_map(|instr| instr.get_args().unwrap_or_default())
+    {
+        *uses.entry(arg).or_insert(0) += 1;
+    }
+    let mut used: HashSet<_> = cfg
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instrs)
+        .flat_map(|instr| {
+            instr
+                .get_args()
+                .unwrap_or_default()
+                .into_iter()
+                .chain(instr.get_dest())
+        })
+        .chain(cfg.args.iter().map(|arg| arg.name.clone()))
+        .collect();
+
+    for i in 0..cfg.blocks.len() {
+        let node = CFGNode::Block(i);
+        let phis: Vec<_> = cfg.blocks[i]
+            .instrs
+            .iter()
+            .filter_map(|instr| match instr {
+                Instruction::Value {
+                    op: ValueOps::Phi,
+                    dest,
+                    op_type,
+                    args,
+                    labels,
+                    ..
+                } => Some((dest.clone(), op_type.clone(), args.clone(), labels.clone())),
+                _ => None,
+            })
+            .collect();
+        if phis.is_empty() {
+            continue;
+        }
+
+        for pred in cfg.graph.neighbors_directed(node, Incoming).sorted() {
+            let Some(pred_block) = cfg.get_block(pred) else {
+                continue;
+            };
+            let assigned_out = &assigned.out_map[&pred];
+            let mut copies = phis
+                .iter()
+                .filter_map(|(dest, op_type, args, labels)| {
+                    let src = labels
+                        .iter()
+                        .position(|label| *label == pred_block.label)
+                        .map(|i| &args[i])?;
+                    (live.contains(dest) && assigned_out.contains(src) && src != dest).then(|| {
+                        PhiCopy {
+                            dest: dest.clone(),
+                            src: src.clone(),
+                            op_type: op_type.clone(),
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            if cfg.graph.neighbors_directed(pred, Outgoing).count() == 1 {
+                let pred_instrs = &mut cfg.get_block_mut(pred).unwrap().instrs;
+                coalesce(&mut copies, pred_instrs, &uses);
+                let instrs = sequentialize(copies, &mut used);
+                let end = pred_instrs.len() - 1;
+                pred_instrs.splice(end..end, instrs);
+            } else {
+                let instrs = sequentialize(copies, &mut used);
+                let start = phis.len();
+                cfg.blocks[i].instrs.splice(start..start, instrs);
+            }
+        }
     }
-    blocks_mut.values().for_each(|block| {
-        block.borrow_mut().instrs.retain(|instr| {
+
+    for block in &mut cfg.blocks {
+        block.instrs.retain(|instr| {
             !matches!(
                 instr,
                 Instruction::Value {
@@ -83,5 +227,5 @@ pub fn remove_phi_nodes(cfg: &mut CFG) {
                 }
             )
         });
-    })
+    }
 }
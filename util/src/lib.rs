@@ -1,3 +1,5 @@
+pub mod value;
+
 use bril_rs::{EffectOps, Instruction, Type, ValueOps};
 
 pub trait CheckOp {
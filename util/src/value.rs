@@ -0,0 +1,179 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use bril_rs::{Instruction, Literal, Type, ValueOps};
+
+/// A value computed by an instruction. Arguments are value numbers by default, or variable
+/// names for expressions compared across blocks
+#[derive(PartialEq, Clone, Debug)]
+pub enum Value<A = usize> {
+    Operation {
+        kind: Type,
+        op: ValueOps,
+        args: Vec<A>,
+    },
+    /// A call to a pure function, equal for equal arguments
+    Call {
+        kind: Type,
+        func: String,
+        args: Vec<A>,
+    },
+    Constant(Literal),
+    Unknown(String),
+}
+
+impl<A: Clone + Ord> Value<A> {
+    /// Sorts the arguments of commutative operations
+    pub fn to_canonical(&self) -> Value<A> {
+        let mut canonical = self.clone();
+        if let Value::Operation {
+            args,
+            op:
+                ValueOps::Add
+                | ValueOps::Mul
+                | ValueOps::Eq
+                | ValueOps::And
+                | ValueOps::Or
+                | ValueOps::Fadd
+                | ValueOps::Fmul
+                | ValueOps::Feq,
+            ..
+        } = &mut canonical
+        {
+            args.sort();
+        }
+        canonical
+    }
+}
+
+impl<A> Value<A> {
+    /// Arguments of operations and calls, empty otherwise
+    pub fn args(&self) -> &[A] {
+        match self {
+            Value::Operation { args, .. } | Value::Call { args, .. } => args,
+            Value::Constant(_) | Value::Unknown(_) => &[],
+        }
+    }
+
+    /// Type of operations and calls, None otherwise
+    pub fn kind(&self) -> Option<&Type> {
+        match self {
+            Value::Operation { kind, .. } | Value::Call { kind, .. } => Some(kind),
+            Value::Constant(_) | Value::Unknown(_) => None,
+        }
+    }
+}
+
+impl Value<String> {
+    /// Returns the canonical operation computed by instr over its variables, None if instr is
+    /// not a pure computation worth reusing
+    pub fn from_instr(instr: &Instruction) -> Option<Self> {
+        let Instruction::Value {
+            op, op_type, args, ..
+        } = instr
+        else {
+            return None;
+        };
+        if matches!(
+            op,
+            ValueOps::Id | ValueOps::Call | ValueOps::Phi | ValueOps::Alloc | ValueOps::Load
+        ) {
+            return None;
+        }
+        Some(
+            Value::Operation {
+                kind: op_type.clone(),
+                op: *op,
+                args: args.clone(),
+            }
+            .to_canonical(),
+        )
+    }
+
+    /// Returns the value of a call with a destination. Only calls to pure functions
+    /// compute a value, which the caller has to check
+    pub fn from_call(instr: &Instruction) -> Option<Self> {
+        match instr {
+            Instruction::Value {
+                op: ValueOps::Call,
+                op_type,
+                args,
+                funcs,
+                ..
+            } => Some(Value::Call {
+                kind: op_type.clone(),
+                func: funcs[0].clone(),
+                args: args.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn uses(&self, var: &str) -> bool {
+        self.args().iter().any(|arg| arg == var)
+    }
+
+    /// Returns an instruction computing the value into dest, None for constants and unknowns
+    pub fn to_instr(&self, dest: &str) -> Option<Instruction> {
+        let (op, funcs) = match self {
+            Value::Operation { op, .. } => (*op, Vec::new()),
+            Value::Call { func, .. } => (ValueOps::Call, vec![func.clone()]),
+            Value::Constant(_) | Value::Unknown(_) => return None,
+        };
+        Some(Instruction::Value {
+            op,
+            dest: dest.to_string(),
+            args: self.args().to_vec(),
+            funcs,
+            labels: Vec::new(),
+            op_type: self.kind()?.clone(),
+            pos: None,
+        })
+    }
+}
+
+impl<A: Hash> Hash for Value<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Operation { kind, op, args } => {
+                kind.hash(state);
+                op.hash(state);
+                args.hash(state);
+            }
+            Value::Call { kind, func, args } => {
+                kind.hash(state);
+                func.hash(state);
+                args.hash(state);
+            }
+            Value::Constant(literal) => {
+                std::mem::discriminant(literal).hash(state);
+                match literal {
+                    Literal::Int(i) => i.hash(state),
+                    Literal::Bool(b) => b.hash(state),
+                    Literal::Float(f) => f.to_bits().hash(state),
+                    Literal::Char(c) => c.hash(state),
+                }
+            }
+            Value::Unknown(name) => name.hash(state),
+        }
+    }
+}
+
+impl<A: Eq> Eq for Value<A> {}
+
+impl<A: fmt::Display> fmt::Display for Value<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Operation { op, .. } => write!(f, "{}", op)?,
+            Value::Call { func, .. } => write!(f, "{} @{}", ValueOps::Call, func)?,
+            Value::Constant(literal) => return write!(f, "const {}", literal),
+            Value::Unknown(name) => return write!(f, "{}", name),
+        }
+        for arg in self.args() {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}